@@ -0,0 +1,338 @@
+use crate::{config, credentials};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::PathBuf;
+
+/// 注册时密码最少字符数
+const MIN_PASSWORD_LEN: usize = 8;
+
+/// 新记住的账号派生密码摘要时 PBKDF2 的迭代次数
+const PASSWORD_HASH_ROUNDS: u32 = 600_000;
+
+/// 在本机登录过的账号。只保存加盐后的密码摘要，用于服务器不可达时验证离线登录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnownAccount {
+    pub username: String,
+    pub user_id: i64,
+    /// 上次登录得到的token，加密后以 base64 保存，恢复连接后继续使用
+    #[serde(default)]
+    sealed_token: String,
+    salt: String,
+    /// 派生 `password_hash` 时的迭代次数，为 0 的账号需要重新在线登录
+    #[serde(default)]
+    rounds: u32,
+    password_hash: String,
+}
+
+fn accounts_path() -> PathBuf {
+    config::data_dir().join("accounts.json")
+}
+
+fn load_accounts() -> Vec<KnownAccount> {
+    match std::fs::read_to_string(accounts_path()) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            println!("[错误] 解析账号文件失败: {}", e);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn save_accounts(accounts: &[KnownAccount]) -> anyhow::Result<()> {
+    let path = accounts_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(accounts)?)?;
+    Ok(())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// 用 PBKDF2-HMAC-SHA256 派生密码摘要，迭代次数让离线暴力破解的代价足够高
+fn hash_password(salt: &str, password: &str, rounds: u32) -> String {
+    let mut hash = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt.as_bytes(), rounds, &mut hash);
+    to_hex(&hash)
+}
+
+impl KnownAccount {
+    /// 解密保存的token
+    pub fn token(&self) -> Option<String> {
+        let sealed = STANDARD.decode(&self.sealed_token).ok()?;
+        let token = credentials::unseal(&sealed)
+            .inspect_err(|e| println!("[错误] 解密账号token失败: {}", e))
+            .ok()?;
+        String::from_utf8(token).ok()
+    }
+}
+
+/// 在线登录成功后记住账号
+pub fn remember(username: &str, password: &str, user_id: i64, token: &str) {
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let salt = to_hex(&salt);
+    let sealed_token = match credentials::seal(token.as_bytes()) {
+        Ok(sealed) => STANDARD.encode(sealed),
+        Err(e) => {
+            println!("[错误] 加密账号token失败: {}", e);
+            String::new()
+        }
+    };
+    let account = KnownAccount {
+        username: username.to_string(),
+        user_id,
+        sealed_token,
+        password_hash: hash_password(&salt, password, PASSWORD_HASH_ROUNDS),
+        salt,
+        rounds: PASSWORD_HASH_ROUNDS,
+    };
+
+    let mut accounts = load_accounts();
+    accounts.retain(|known| known.username != username);
+    accounts.push(account);
+    if let Err(e) = save_accounts(&accounts) {
+        println!("[错误] 保存账号失败: {}", e);
+    }
+}
+
+/// token 刷新后更新记住的账号，离线登录后恢复连接时使用新的 token
+pub fn update_token(user_id: i64, token: &str) {
+    let mut accounts = load_accounts();
+    let Some(account) = accounts
+        .iter_mut()
+        .find(|account| account.user_id == user_id)
+    else {
+        return;
+    };
+    match credentials::seal(token.as_bytes()) {
+        Ok(sealed) => account.sealed_token = STANDARD.encode(sealed),
+        Err(e) => {
+            println!("[错误] 加密账号token失败: {}", e);
+            return;
+        }
+    }
+    if let Err(e) = save_accounts(&accounts) {
+        println!("[错误] 保存账号失败: {}", e);
+    }
+}
+
+/// 服务器不可达时，用本机记住的账号验证用户名和密码
+pub fn offline_login(username: &str, password: &str) -> Option<KnownAccount> {
+    load_accounts().into_iter().find(|account| {
+        account.username == username
+            && account.rounds > 0
+            && account.password_hash == hash_password(&account.salt, password, account.rounds)
+    })
+}
+
+/// 密码强度，用于注册时的提示和校验
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PasswordStrength {
+    Empty,
+    Weak,
+    Medium,
+    Strong,
+}
+
+/// 按长度和包含的字符种类（小写、大写、数字、符号）评估密码强度
+pub fn password_strength(password: &str) -> PasswordStrength {
+    if password.is_empty() {
+        return PasswordStrength::Empty;
+    }
+    let kinds = [
+        password.chars().any(|c| c.is_ascii_lowercase()),
+        password.chars().any(|c| c.is_ascii_uppercase()),
+        password.chars().any(|c| c.is_ascii_digit()),
+        password.chars().any(|c| !c.is_ascii_alphanumeric()),
+    ]
+    .into_iter()
+    .filter(|&has| has)
+    .count();
+    let length = password.chars().count();
+    if length < MIN_PASSWORD_LEN || kinds < 2 {
+        PasswordStrength::Weak
+    } else if length >= 12 && kinds >= 3 {
+        PasswordStrength::Strong
+    } else {
+        PasswordStrength::Medium
+    }
+}
+
+/// 注册表单的内容
+pub struct Registration {
+    pub username: String,
+    pub password: String,
+    pub confirm_password: String,
+    pub email: String,
+    pub phone: String,
+}
+
+/// 在本地校验注册表单，返回显示给用户的错误信息
+pub fn validate_registration(form: &Registration) -> Result<(), String> {
+    let username_len = form.username.chars().count();
+    if username_len == 0 {
+        return Err("请输入用户名".to_string());
+    }
+    if !(3..=20).contains(&username_len) {
+        return Err("用户名长度应为3到20个字符".to_string());
+    }
+    if form.username.chars().any(char::is_whitespace) {
+        return Err("用户名不能包含空格".to_string());
+    }
+    if form.password.is_empty() {
+        return Err("请输入密码".to_string());
+    }
+    if form.password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(format!("密码至少需要{}个字符", MIN_PASSWORD_LEN));
+    }
+    if password_strength(&form.password) < PasswordStrength::Medium {
+        return Err("密码太简单，请组合使用字母、数字或符号".to_string());
+    }
+    if form.password != form.confirm_password {
+        return Err("两次输入的密码不一致".to_string());
+    }
+    if !form.email.is_empty() && !is_valid_email(&form.email) {
+        return Err("邮箱格式不正确".to_string());
+    }
+    if !form.phone.is_empty() && !is_valid_phone(&form.phone) {
+        return Err("手机号格式不正确".to_string());
+    }
+    Ok(())
+}
+
+fn is_valid_email(email: &str) -> bool {
+    match email.split_once('@') {
+        Some((name, domain)) => {
+            !name.is_empty()
+                && !email.contains(char::is_whitespace)
+                && domain.split('.').count() >= 2
+                && domain.split('.').all(|part| !part.is_empty())
+        }
+        None => false,
+    }
+}
+
+fn is_valid_phone(phone: &str) -> bool {
+    let digits = phone.strip_prefix('+').unwrap_or(phone);
+    (6..=15).contains(&digits.len()) && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form() -> Registration {
+        Registration {
+            username: "alice".to_string(),
+            password: "secret123".to_string(),
+            confirm_password: "secret123".to_string(),
+            email: String::new(),
+            phone: String::new(),
+        }
+    }
+
+    #[test]
+    fn password_strength_levels() {
+        assert_eq!(password_strength(""), PasswordStrength::Empty);
+        assert_eq!(password_strength("abc1"), PasswordStrength::Weak);
+        assert_eq!(password_strength("abcdefghijkl"), PasswordStrength::Weak);
+        assert_eq!(password_strength("secret123"), PasswordStrength::Medium);
+        assert_eq!(password_strength("Secret123"), PasswordStrength::Medium);
+        assert_eq!(password_strength("Secret123456"), PasswordStrength::Strong);
+        assert_eq!(
+            password_strength("密码密码密码密码1"),
+            PasswordStrength::Medium
+        );
+    }
+
+    #[test]
+    fn valid_form_passes() {
+        assert_eq!(validate_registration(&form()), Ok(()));
+        let with_contacts = Registration {
+            email: "alice@example.com".to_string(),
+            phone: "+8613800138000".to_string(),
+            ..form()
+        };
+        assert_eq!(validate_registration(&with_contacts), Ok(()));
+    }
+
+    #[test]
+    fn rejects_bad_username() {
+        for username in ["", "ab", "a".repeat(21).as_str(), "ali ce"] {
+            let form = Registration {
+                username: username.to_string(),
+                ..form()
+            };
+            assert!(validate_registration(&form).is_err(), "{:?}", username);
+        }
+        let chinese = Registration {
+            username: "张三".repeat(2),
+            ..form()
+        };
+        assert_eq!(validate_registration(&chinese), Ok(()));
+    }
+
+    #[test]
+    fn rejects_short_or_weak_password() {
+        for password in ["", "abc123", "abcdefghij"] {
+            let form = Registration {
+                password: password.to_string(),
+                confirm_password: password.to_string(),
+                ..form()
+            };
+            assert!(validate_registration(&form).is_err(), "{:?}", password);
+        }
+    }
+
+    #[test]
+    fn rejects_mismatched_confirmation() {
+        let form = Registration {
+            confirm_password: "secret124".to_string(),
+            ..form()
+        };
+        assert_eq!(
+            validate_registration(&form),
+            Err("两次输入的密码不一致".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_bad_email_and_phone() {
+        for email in [
+            "alice",
+            "@example.com",
+            "alice@example",
+            "alice@.com",
+            "a lice@x.com",
+        ] {
+            let form = Registration {
+                email: email.to_string(),
+                ..form()
+            };
+            assert_eq!(
+                validate_registration(&form),
+                Err("邮箱格式不正确".to_string()),
+                "{:?}",
+                email
+            );
+        }
+        for phone in ["12345", "138-0013-8000", "+", "1234567890123456"] {
+            let form = Registration {
+                phone: phone.to_string(),
+                ..form()
+            };
+            assert_eq!(
+                validate_registration(&form),
+                Err("手机号格式不正确".to_string()),
+                "{:?}",
+                phone
+            );
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use super::ErrorResponse;
+use reqwest::StatusCode;
+use std::fmt;
+use std::time::Duration;
+
+/// 错误信息中保留的响应体长度，避免把整个页面打进日志
+const BODY_SNIPPET_LEN: usize = 200;
+
+/// 接口调用失败的原因，调用方据此决定重新登录、退避重试或者提示用户
+#[derive(Debug)]
+pub enum ApiError {
+    /// 无法连接服务器
+    Network(reqwest::Error),
+    /// 请求超时
+    Timeout,
+    /// 未登录或 token 已失效，需要重新登录
+    Unauthorized,
+    NotFound,
+    /// 与已有数据冲突，例如注册时用户名已被占用
+    Conflict,
+    /// 请求过于频繁，`retry_after` 为服务端建议的等待时间
+    RateLimited {
+        retry_after: Option<Duration>,
+    },
+    /// 服务端返回的其他错误
+    Server {
+        code: i32,
+        reason: String,
+        description: String,
+    },
+    /// 响应内容无法解析
+    Decode {
+        body: String,
+    },
+    /// 上传或下载时读写本地文件失败
+    File(std::io::Error),
+}
+
+impl ApiError {
+    /// 根据失败响应的 HTTP 状态码和 `ErrorInfo.code` 确定错误类型，
+    /// 响应体不是 `ErrorResponse` 时以 HTTP 状态码为准
+    pub(super) fn from_response(
+        status: StatusCode,
+        retry_after: Option<Duration>,
+        body: &str,
+    ) -> Self {
+        let (code, reason, description) = match serde_json::from_str::<ErrorResponse>(body) {
+            Ok(response) => (
+                response.error.code,
+                response.error.reason,
+                response.error.description,
+            ),
+            Err(_) => (
+                status.as_u16() as i32,
+                status.canonical_reason().unwrap_or_default().to_string(),
+                snippet(body),
+            ),
+        };
+        let is = |expected: StatusCode| status == expected || code == expected.as_u16() as i32;
+        if is(StatusCode::UNAUTHORIZED) {
+            Self::Unauthorized
+        } else if is(StatusCode::NOT_FOUND) {
+            Self::NotFound
+        } else if is(StatusCode::CONFLICT) {
+            Self::Conflict
+        } else if is(StatusCode::TOO_MANY_REQUESTS) {
+            Self::RateLimited { retry_after }
+        } else {
+            Self::Server {
+                code,
+                reason,
+                description,
+            }
+        }
+    }
+
+    pub(super) fn decode(body: &str) -> Self {
+        Self::Decode {
+            body: snippet(body),
+        }
+    }
+
+    /// 是否为无法连接服务器导致的错误，此时可以使用本地缓存离线浏览
+    pub fn is_unreachable(&self) -> bool {
+        matches!(self, Self::Network(_) | Self::Timeout)
+    }
+}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            Self::Timeout
+        } else if error.is_decode() {
+            Self::Decode {
+                body: error.to_string(),
+            }
+        } else {
+            Self::Network(error)
+        }
+    }
+}
+
+impl From<std::io::Error> for ApiError {
+    fn from(error: std::io::Error) -> Self {
+        Self::File(error)
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Network(e) => write!(f, "无法连接服务器: {}", e),
+            Self::Timeout => write!(f, "请求超时"),
+            Self::Unauthorized => write!(f, "登录已失效，请重新登录"),
+            Self::NotFound => write!(f, "请求的资源不存在"),
+            Self::Conflict => write!(f, "数据已存在"),
+            Self::RateLimited {
+                retry_after: Some(delay),
+            } => write!(f, "请求过于频繁，请{}秒后再试", delay.as_secs().max(1)),
+            Self::RateLimited { retry_after: None } => write!(f, "请求过于频繁，请稍后再试"),
+            Self::Server {
+                code,
+                reason,
+                description,
+            } => write!(f, "服务器错误 {} {}: {}", code, reason, description),
+            Self::Decode { body } => write!(f, "无法解析服务器响应: {}", body),
+            Self::File(e) => write!(f, "读写本地文件失败: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Network(e) => Some(e),
+            Self::File(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+fn snippet(body: &str) -> String {
+    let mut snippet: String = body.chars().take(BODY_SNIPPET_LEN).collect();
+    if snippet.len() < body.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_body(code: i32, reason: &str, description: &str) -> String {
+        format!(
+            r#"{{"error":{{"code":{},"reason":"{}","description":"{}"}}}}"#,
+            code, reason, description
+        )
+    }
+
+    #[test]
+    fn maps_http_status_without_error_body() {
+        assert!(matches!(
+            ApiError::from_response(StatusCode::UNAUTHORIZED, None, ""),
+            ApiError::Unauthorized
+        ));
+        assert!(matches!(
+            ApiError::from_response(StatusCode::NOT_FOUND, None, "<html></html>"),
+            ApiError::NotFound
+        ));
+        assert!(matches!(
+            ApiError::from_response(StatusCode::CONFLICT, None, ""),
+            ApiError::Conflict
+        ));
+    }
+
+    #[test]
+    fn maps_error_code_in_body() {
+        // HTTP 状态码只说明请求有误，具体原因看响应体中的错误码
+        let body = error_body(401, "Unauthorized", "token expired");
+        assert!(matches!(
+            ApiError::from_response(StatusCode::FORBIDDEN, None, &body),
+            ApiError::Unauthorized
+        ));
+        let body = error_body(409, "Conflict", "username taken");
+        assert!(matches!(
+            ApiError::from_response(StatusCode::BAD_REQUEST, None, &body),
+            ApiError::Conflict
+        ));
+    }
+
+    #[test]
+    fn rate_limited_keeps_retry_after() {
+        let retry_after = Some(Duration::from_secs(5));
+        match ApiError::from_response(StatusCode::TOO_MANY_REQUESTS, retry_after, "") {
+            ApiError::RateLimited { retry_after: delay } => assert_eq!(delay, retry_after),
+            other => panic!("不是限流错误: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn other_errors_keep_body_details() {
+        let body = error_body(1001, "Bad Request", "参数错误");
+        match ApiError::from_response(StatusCode::BAD_REQUEST, None, &body) {
+            ApiError::Server {
+                code,
+                reason,
+                description,
+            } => {
+                assert_eq!(code, 1001);
+                assert_eq!(reason, "Bad Request");
+                assert_eq!(description, "参数错误");
+            }
+            other => panic!("不是服务器错误: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unparsable_body_falls_back_to_status() {
+        let body = "x".repeat(BODY_SNIPPET_LEN + 50);
+        match ApiError::from_response(StatusCode::INTERNAL_SERVER_ERROR, None, &body) {
+            ApiError::Server {
+                code,
+                reason,
+                description,
+            } => {
+                assert_eq!(code, 500);
+                assert_eq!(reason, "Internal Server Error");
+                assert_eq!(description.chars().count(), BODY_SNIPPET_LEN + 1);
+                assert!(description.ends_with('…'));
+            }
+            other => panic!("不是服务器错误: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn only_connection_errors_are_unreachable() {
+        assert!(ApiError::Timeout.is_unreachable());
+        assert!(!ApiError::Unauthorized.is_unreachable());
+        assert!(!ApiError::decode("oops").is_unreachable());
+    }
+}
@@ -1,3 +1,7 @@
+mod error;
 mod network;
+pub mod request;
+mod token;
 
+pub use error::ApiError;
 pub use network::*;
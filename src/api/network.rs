@@ -1,4 +1,16 @@
+use super::{token, ApiError};
+use crate::config::HttpConfig;
+use futures_util::StreamExt;
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::broadcast;
+
+/// 上传时每次读取的字节数
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoginRequest {
@@ -6,11 +18,74 @@ pub struct LoginRequest {
     pub password: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct LoginResponse {
     pub success: bool,
     pub message: String,
     pub token: Option<String>,
+    /// 用于在 token 过期后换取新 token，服务端不支持刷新时为空
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    pub user_id: Option<i64>,
+}
+
+/// 调试输出中只显示是否带有 token，不输出 token 本身
+impl std::fmt::Debug for LoginResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoginResponse")
+            .field("success", &self.success)
+            .field("message", &self.message)
+            .field("token", &self.token.as_ref().map(|_| "<hidden>"))
+            .field(
+                "refresh_token",
+                &self.refresh_token.as_ref().map(|_| "<hidden>"),
+            )
+            .field("user_id", &self.user_id)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RefreshResponse {
+    pub token: String,
+    /// 服务端轮换 refresh token 时返回新的，否则继续使用原来的
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+/// 登录状态的变化，WebSocket 和界面据此使用新 token 或回到登录窗口
+#[derive(Debug, Clone)]
+pub enum AuthEvent {
+    /// token 已刷新
+    Refreshed {
+        token: String,
+        refresh_token: Option<String>,
+    },
+    /// token 已失效且无法刷新，需要重新登录
+    Expired,
+}
+
+/// 注册请求，邮箱和手机号可以不填
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegisterRequest {
+    pub username: String,
+    pub password: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phone: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegisterResponse {
+    pub success: bool,
+    #[serde(default)]
+    pub message: String,
     pub user_id: Option<i64>,
 }
 
@@ -18,7 +93,69 @@ pub struct LoginResponse {
 pub struct FriendInfo {
     pub id: i64,
     pub username: String,
+    /// 头像地址，没有设置头像时为空
+    #[serde(default)]
+    pub avatar_url: Option<String>,
 }
+
+/// 群成员
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupMember {
+    pub user_id: i64,
+    pub username: String,
+    #[serde(default)]
+    pub avatar_url: Option<String>,
+}
+
+/// `/api/users/{id}` 返回的用户资料
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserProfile {
+    pub id: i64,
+    pub username: String,
+    #[serde(default)]
+    pub avatar_url: Option<String>,
+}
+
+/// 群信息。群列表接口可能不返回成员，此时 `members` 为空
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupInfo {
+    pub id: i64,
+    pub name: String,
+    pub owner_id: i64,
+    #[serde(default)]
+    pub members: Vec<GroupMember>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateGroupRequest {
+    pub name: String,
+    pub member_ids: Vec<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InviteRequest {
+    pub user_ids: Vec<i64>,
+}
+
+/// `/api/presence` 返回的好友在线状态快照
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PresenceInfo {
+    pub user_id: i64,
+    pub status: String,
+    pub last_seen: Option<i64>,
+}
+
+/// `/api/upload` 返回的已上传文件，发送文件消息时带上 `file_path`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadResponse {
+    pub file_path: String,
+    pub file_name: String,
+    pub file_size: i64,
+}
+
+/// 上传、下载的进度回调，参数为已传输和总共的字节数，总字节数未知时为0
+pub type Progress = Arc<dyn Fn(u64, u64) + Send + Sync>;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessageResponse {
     pub id: i64,
@@ -35,6 +172,17 @@ pub struct MessageResponse {
     pub message_type: Option<String>,
 }
 
+/// 每页聊天记录的条数
+pub const HISTORY_PAGE_SIZE: u32 = 30;
+
+/// 一页聊天记录
+#[derive(Debug, Clone)]
+pub struct HistoryPage {
+    pub messages: Vec<MessageResponse>,
+    /// 是否还有更早的消息
+    pub has_more: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub error: ErrorInfo,
@@ -47,36 +195,252 @@ pub struct ErrorInfo {
     pub description: String,
 }
 
+pub type ApiResult<T> = Result<T, ApiError>;
+
 pub struct NetworkClient {
     base_url: String,
     token: std::sync::Mutex<Option<String>>,
-    client: reqwest::blocking::Client,
+    refresh_token: std::sync::Mutex<Option<String>>,
+    /// 保证同一时间只有一个刷新请求，其他请求等待它的结果
+    refresh_lock: tokio::sync::Mutex<()>,
+    auth_tx: broadcast::Sender<AuthEvent>,
+    client: reqwest::Client,
+    config: HttpConfig,
 }
 
 impl NetworkClient {
     pub fn new(base_url: String) -> Self {
+        Self::with_config(base_url, HttpConfig::default())
+    }
+
+    pub fn with_config(base_url: String, config: HttpConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .connect_timeout(config.connect_timeout)
+            .timeout(config.request_timeout)
+            .build()
+            .unwrap_or_else(|e| {
+                println!("[错误] 创建HTTP客户端失败，使用默认配置: {}", e);
+                reqwest::Client::new()
+            });
+        let (auth_tx, _) = broadcast::channel(16);
         Self {
             base_url,
             token: std::sync::Mutex::new(None),
-            client: reqwest::blocking::Client::new(),
+            refresh_token: std::sync::Mutex::new(None),
+            refresh_lock: tokio::sync::Mutex::new(()),
+            auth_tx,
+            client,
+            config,
+        }
+    }
+
+    /// 发送请求，返回状态码、Retry-After 和响应体；被限流时按 Retry-After 等待后自动重试
+    async fn send_raw(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> ApiResult<(StatusCode, Option<Duration>, String)> {
+        let mut attempt = 0;
+        loop {
+            let Some(builder) = request.try_clone() else {
+                // 请求体不能复制时只发送一次
+                return Self::read(request.send().await?).await;
+            };
+            let (status, retry_after, body) = Self::read(builder.send().await?).await?;
+            if status != StatusCode::TOO_MANY_REQUESTS || attempt >= self.config.rate_limit_retries
+            {
+                return Ok((status, retry_after, body));
+            }
+            let delay = retry_after.unwrap_or(self.config.default_retry_after);
+            if delay > self.config.max_retry_after {
+                return Ok((status, retry_after, body));
+            }
+            attempt += 1;
+            println!(
+                "[DEBUG] Rate limited, retrying in {:.1}s (attempt {})",
+                delay.as_secs_f64(),
+                attempt
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn read(
+        response: reqwest::Response,
+    ) -> ApiResult<(StatusCode, Option<Duration>, String)> {
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let body = response.text().await?;
+        Ok((status, retry_after, body))
+    }
+
+    /// 发送请求，失败的状态码转换为 `ApiError`，成功时返回响应体
+    async fn send(&self, request: reqwest::RequestBuilder) -> ApiResult<String> {
+        let (status, retry_after, body) = self.send_raw(request).await?;
+        if status.is_success() {
+            Ok(body)
+        } else {
+            Err(ApiError::from_response(status, retry_after, &body))
+        }
+    }
+
+    /// 发送需要登录的请求：token 即将过期时先刷新，被拒绝（401）时刷新 token 后重试一次
+    async fn send_authorized(
+        &self,
+        build: impl Fn(&str) -> reqwest::RequestBuilder,
+    ) -> ApiResult<String> {
+        let token = self.fresh_token().await?;
+        match self.send(build(&token)).await {
+            Err(ApiError::Unauthorized) => {
+                println!("[DEBUG] Token rejected, refreshing and retrying");
+                self.refresh(&token).await?;
+                let token = self.get_token().ok_or(ApiError::Unauthorized)?;
+                self.send(build(&token)).await
+            }
+            result => result,
+        }
+    }
+
+    /// 与 `send_authorized` 相同，但成功时直接返回响应，由调用方流式读取响应体
+    async fn send_authorized_streaming(
+        &self,
+        build: impl Fn(&str) -> reqwest::RequestBuilder,
+    ) -> ApiResult<reqwest::Response> {
+        let token = self.fresh_token().await?;
+        let mut response = build(&token).send().await?;
+        if response.status() == StatusCode::UNAUTHORIZED {
+            println!("[DEBUG] Token rejected, refreshing and retrying");
+            self.refresh(&token).await?;
+            let token = self.get_token().ok_or(ApiError::Unauthorized)?;
+            response = build(&token).send().await?;
+        }
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        let (status, retry_after, body) = Self::read(response).await?;
+        Err(ApiError::from_response(status, retry_after, &body))
+    }
+
+    /// 当前的 token；根据 JWT 的 `exp` 判断快要过期时先刷新
+    async fn fresh_token(&self) -> ApiResult<String> {
+        let token = self.get_token().ok_or(ApiError::Unauthorized)?;
+        let expiring = token::refresh_delay(&token, self.config.token_refresh_margin)
+            .is_some_and(|delay| delay.is_zero());
+        if !expiring || !self.has_refresh_token() {
+            return Ok(token);
+        }
+        println!("[DEBUG] Token is about to expire, refreshing");
+        self.refresh(&token).await?;
+        self.get_token().ok_or(ApiError::Unauthorized)
+    }
+
+    /// 用 refresh token 换取新 token。`stale` 是调用方手上已失效的 token，
+    /// 若其他请求已经刷新过，直接使用新的 token 而不再重复刷新。
+    /// refresh token 同样失效时通知 `AuthEvent::Expired` 并返回 `ApiError::Unauthorized`
+    pub async fn refresh(&self, stale: &str) -> ApiResult<()> {
+        let _guard = self.refresh_lock.lock().await;
+        match self.get_token() {
+            Some(current) if current != stale => return Ok(()),
+            Some(_) => {}
+            // 已经退出登录
+            None => return Err(ApiError::Unauthorized),
+        }
+        let Some(refresh_token) = self.refresh_token.lock().unwrap().clone() else {
+            println!("[DEBUG] No refresh token, session expired");
+            let _ = self.auth_tx.send(AuthEvent::Expired);
+            return Err(ApiError::Unauthorized);
+        };
+
+        println!("[DEBUG] Refreshing token");
+        let request = self
+            .client
+            .post(format!("{}/api/refresh", self.base_url))
+            .json(&RefreshRequest { refresh_token });
+        let response = match self.send(request).await {
+            Ok(body) => decode::<RefreshResponse>(&body)?,
+            Err(ApiError::Unauthorized) => {
+                println!("[DEBUG] Refresh token rejected, session expired");
+                let _ = self.auth_tx.send(AuthEvent::Expired);
+                return Err(ApiError::Unauthorized);
+            }
+            Err(e) => return Err(e),
+        };
+        println!("[DEBUG] Token refreshed");
+        *self.token.lock().unwrap() = Some(response.token.clone());
+        if let Some(refresh_token) = &response.refresh_token {
+            *self.refresh_token.lock().unwrap() = Some(refresh_token.clone());
         }
+        let _ = self.auth_tx.send(AuthEvent::Refreshed {
+            token: response.token,
+            refresh_token: self.refresh_token.lock().unwrap().clone(),
+        });
+        Ok(())
     }
 
-    pub fn login(&self, username: String, password: String) -> anyhow::Result<LoginResponse> {
+    /// 在 token 过期前主动刷新，这样长时间没有请求时 WebSocket 重连也能用上有效的 token。
+    /// 没有 refresh token、token 不带过期时间或者 refresh token 失效时结束
+    pub async fn keep_token_fresh(&self) {
+        loop {
+            let Some(token) = self.get_token() else {
+                return;
+            };
+            if !self.has_refresh_token() {
+                return;
+            }
+            let Some(delay) = token::refresh_delay(&token, self.config.token_refresh_margin) else {
+                return;
+            };
+            println!("[DEBUG] Token will be refreshed in {}s", delay.as_secs());
+            tokio::time::sleep(delay).await;
+            match self.refresh(&token).await {
+                Ok(()) => {}
+                Err(ApiError::Unauthorized) => return,
+                Err(e) => {
+                    println!("[DEBUG] Failed to refresh token: {}", e);
+                    tokio::time::sleep(self.config.token_refresh_retry).await;
+                }
+            }
+        }
+    }
+
+    /// 订阅 token 刷新和登录失效事件
+    pub fn subscribe_auth_events(&self) -> broadcast::Receiver<AuthEvent> {
+        self.auth_tx.subscribe()
+    }
+
+    pub async fn login(&self, username: String, password: String) -> ApiResult<LoginResponse> {
         println!("[DEBUG] Attempting login for user: {}", &username);
         let request = LoginRequest { username, password };
 
-        let response = self
-            .client
-            .post(format!("{}/api/login", self.base_url))
-            .json(&request)
-            .send()?;
+        let (status, retry_after, body) = self
+            .send_raw(
+                self.client
+                    .post(format!("{}/api/login", self.base_url))
+                    .json(&request),
+            )
+            .await?;
+        println!("[DEBUG] Login response status: {}", status);
 
-        let response = response.json::<LoginResponse>()?;
+        let response = if status.is_success() {
+            decode::<LoginResponse>(&body)?
+        } else {
+            // 用户名或密码错误时，服务端以失败状态码返回带说明的登录响应
+            match serde_json::from_str::<LoginResponse>(&body) {
+                Ok(response) if !response.success && status != StatusCode::TOO_MANY_REQUESTS => {
+                    response
+                }
+                _ => return Err(ApiError::from_response(status, retry_after, &body)),
+            }
+        };
         if response.success {
             if let Some(token) = response.token.clone() {
-                println!("[DEBUG] Login successful, token received: {}", token);
+                println!("[DEBUG] Login successful, token received");
                 *self.token.lock().unwrap() = Some(token);
+                *self.refresh_token.lock().unwrap() = response.refresh_token.clone();
             } else {
                 println!("[DEBUG] Login successful but no token received");
             }
@@ -86,18 +450,84 @@ impl NetworkClient {
         Ok(response)
     }
 
+    pub async fn register(&self, request: RegisterRequest) -> ApiResult<RegisterResponse> {
+        println!("[DEBUG] Attempting to register user: {}", &request.username);
+        let (status, retry_after, body) = self
+            .send_raw(
+                self.client
+                    .post(format!("{}/api/register", self.base_url))
+                    .json(&request),
+            )
+            .await?;
+        println!("[DEBUG] Register response status: {}", status);
+
+        let response = if status.is_success() {
+            decode::<RegisterResponse>(&body)?
+        } else {
+            // 用户名已存在等业务错误，服务端以失败状态码返回带说明的注册响应
+            match serde_json::from_str::<RegisterResponse>(&body) {
+                Ok(response) if !response.success && status != StatusCode::TOO_MANY_REQUESTS => {
+                    response
+                }
+                _ => return Err(ApiError::from_response(status, retry_after, &body)),
+            }
+        };
+        if response.success {
+            println!(
+                "[DEBUG] Register successful, user id: {:?}",
+                response.user_id
+            );
+        } else {
+            println!("[DEBUG] Register failed: {}", response.message);
+        }
+        Ok(response)
+    }
+
+    /// 检查当前 token 是否仍然有效。服务端没有单独的校验接口，用需要登录的好友列表接口代替
+    /// token 已失效但 refresh token 仍有效时会自动刷新
+    pub async fn verify_token(&self) -> ApiResult<()> {
+        println!("[DEBUG] Verifying saved token");
+        self.send_authorized(|token| {
+            self.client
+                .get(format!("{}/api/friends", self.base_url))
+                .header("Authorization", format!("Bearer {}", token))
+        })
+        .await
+        .map(|_| ())
+    }
+
     pub fn get_token(&self) -> Option<String> {
         self.token.lock().unwrap().clone()
     }
 
-    pub fn get_friend_list(&self) -> anyhow::Result<Vec<FriendInfo>> {
-        let token = self.get_token().unwrap_or_default();
-        println!(
-            "[DEBUG] Attempting to get friend list with token: {}",
-            token
-        );
+    /// 离线登录时使用上次保存的token
+    pub fn set_token(&self, token: String) {
+        *self.token.lock().unwrap() = Some(token);
+    }
+
+    /// 自动登录时使用保存的 refresh token
+    pub fn set_refresh_token(&self, refresh_token: Option<String>) {
+        *self.refresh_token.lock().unwrap() = refresh_token;
+    }
+
+    pub fn get_refresh_token(&self) -> Option<String> {
+        self.refresh_token.lock().unwrap().clone()
+    }
+
+    fn has_refresh_token(&self) -> bool {
+        self.refresh_token.lock().unwrap().is_some()
+    }
+
+    /// 退出登录时清除 token，之后的请求不再携带登录信息
+    pub fn clear_token(&self) {
+        *self.token.lock().unwrap() = None;
+        *self.refresh_token.lock().unwrap() = None;
+    }
+
+    pub async fn get_friend_list(&self) -> ApiResult<Vec<FriendInfo>> {
+        println!("[DEBUG] Attempting to get friend list");
 
-        let response = self.client
+        let response_text = self.send_authorized(|token| self.client
             .get(format!("{}/api/friends", self.base_url))
             .header("Authorization", format!("Bearer {}", token))
             .header("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,image/apng,*/*;q=0.8,application/signed-exchange;v=b3;q=0.7")
@@ -105,64 +535,362 @@ impl NetworkClient {
             .header("Cache-Control", "max-age=0")
             .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/135.0.0.0 Safari/537.36")
             .header("Upgrade-Insecure-Requests", "1")
-            .header("Proxy-Connection", "keep-alive")
-            .send()?;
-
-        let status = response.status();
-        println!("[DEBUG] Friend list response status: {}", status);
-        let response_text = response.text()?;
+            .header("Proxy-Connection", "keep-alive")).await?;
         println!("[DEBUG] Friend list response body: {}", response_text);
 
-        if status.is_success() {
-            let response = serde_json::from_str::<Vec<FriendInfo>>(&response_text)?;
-            println!("[DEBUG] Successfully got {} friends", response.len());
-            Ok(response)
+        let response = decode::<Vec<FriendInfo>>(&response_text)?;
+        println!("[DEBUG] Successfully got {} friends", response.len());
+        Ok(response)
+    }
+
+    /// 获取用户资料，用于自己的头像等好友列表之外的信息
+    pub async fn get_user(&self, user_id: i64) -> ApiResult<UserProfile> {
+        println!("[DEBUG] Attempting to get user {}", user_id);
+        let response_text = self
+            .send_authorized(|token| {
+                self.client
+                    .get(format!("{}/api/users/{}", self.base_url, user_id))
+                    .header("Authorization", format!("Bearer {}", token))
+            })
+            .await?;
+        decode::<UserProfile>(&response_text)
+    }
+
+    /// 下载头像图片。头像地址可以是完整的 URL，也可以是服务器上的路径
+    pub async fn fetch_avatar(&self, url: &str) -> ApiResult<Vec<u8>> {
+        let url = if url.starts_with("http://") || url.starts_with("https://") {
+            url.to_string()
         } else {
-            let error = serde_json::from_str::<ErrorResponse>(&response_text)?;
-            Err(anyhow::anyhow!(
-                "Server error: {} - {}",
-                error.error.reason,
-                error.error.description
-            ))
+            format!("{}/{}", self.base_url, url.trim_start_matches('/'))
+        };
+        println!("[DEBUG] Attempting to fetch avatar {}", url);
+        let response = self
+            .send_authorized_streaming(|token| {
+                self.client
+                    .get(&url)
+                    .header("Authorization", format!("Bearer {}", token))
+            })
+            .await?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    /// 获取我加入的群
+    pub async fn get_groups(&self) -> ApiResult<Vec<GroupInfo>> {
+        println!("[DEBUG] Attempting to get groups");
+        let response_text = self
+            .send_authorized(|token| {
+                self.client
+                    .get(format!("{}/api/groups", self.base_url))
+                    .header("Authorization", format!("Bearer {}", token))
+            })
+            .await?;
+        let groups = decode::<Vec<GroupInfo>>(&response_text)?;
+        println!("[DEBUG] Successfully got {} groups", groups.len());
+        Ok(groups)
+    }
+
+    /// 获取群信息和成员
+    pub async fn get_group(&self, group_id: i64) -> ApiResult<GroupInfo> {
+        println!("[DEBUG] Attempting to get group {}", group_id);
+        let response_text = self
+            .send_authorized(|token| {
+                self.client
+                    .get(format!("{}/api/groups/{}", self.base_url, group_id))
+                    .header("Authorization", format!("Bearer {}", token))
+            })
+            .await?;
+        decode::<GroupInfo>(&response_text)
+    }
+
+    /// 创建群，创建者自动成为群主和成员
+    pub async fn create_group(&self, name: String, member_ids: Vec<i64>) -> ApiResult<GroupInfo> {
+        println!("[DEBUG] Attempting to create group: {}", name);
+        let request = CreateGroupRequest { name, member_ids };
+        let response_text = self
+            .send_authorized(|token| {
+                self.client
+                    .post(format!("{}/api/groups", self.base_url))
+                    .header("Authorization", format!("Bearer {}", token))
+                    .json(&request)
+            })
+            .await?;
+        decode::<GroupInfo>(&response_text)
+    }
+
+    /// 邀请好友加入群
+    pub async fn invite_to_group(&self, group_id: i64, user_ids: Vec<i64>) -> ApiResult<()> {
+        println!(
+            "[DEBUG] Attempting to invite {:?} to group {}",
+            user_ids, group_id
+        );
+        let request = InviteRequest { user_ids };
+        self.send_authorized(|token| {
+            self.client
+                .post(format!("{}/api/groups/{}/members", self.base_url, group_id))
+                .header("Authorization", format!("Bearer {}", token))
+                .json(&request)
+        })
+        .await
+        .map(|_| ())
+    }
+
+    /// 退出群
+    pub async fn leave_group(&self, group_id: i64) -> ApiResult<()> {
+        println!("[DEBUG] Attempting to leave group {}", group_id);
+        self.send_authorized(|token| {
+            self.client
+                .post(format!("{}/api/groups/{}/leave", self.base_url, group_id))
+                .header("Authorization", format!("Bearer {}", token))
+        })
+        .await
+        .map(|_| ())
+    }
+
+    pub async fn get_presence_snapshot(&self) -> ApiResult<Vec<PresenceInfo>> {
+        println!("[DEBUG] Attempting to get presence snapshot");
+        let response_text = self
+            .send_authorized(|token| {
+                self.client
+                    .get(format!("{}/api/presence", self.base_url))
+                    .header("Authorization", format!("Bearer {}", token))
+            })
+            .await?;
+
+        let response = decode::<Vec<PresenceInfo>>(&response_text)?;
+        println!("[DEBUG] Successfully got {} presence items", response.len());
+        Ok(response)
+    }
+
+    /// 以 multipart 上传本地文件，边读边发，每发出一块调用一次 `on_progress`
+    pub async fn upload_file(
+        &self,
+        path: &Path,
+        on_progress: Progress,
+    ) -> ApiResult<UploadResponse> {
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "file".to_string());
+        let total = tokio::fs::metadata(path).await?.len();
+        println!(
+            "[DEBUG] Attempting to upload {} ({} bytes)",
+            file_name, total
+        );
+        let response_text = self
+            .send_authorized(|token| {
+                let body = file_body(path.to_path_buf(), total, on_progress.clone());
+                let part = reqwest::multipart::Part::stream_with_length(body, total)
+                    .file_name(file_name.clone());
+                self.client
+                    .post(format!("{}/api/upload", self.base_url))
+                    .header("Authorization", format!("Bearer {}", token))
+                    .timeout(self.config.transfer_timeout)
+                    .multipart(reqwest::multipart::Form::new().part("file", part))
+            })
+            .await?;
+        let uploaded = decode::<UploadResponse>(&response_text)?;
+        println!("[DEBUG] Uploaded to {}", uploaded.file_path);
+        Ok(uploaded)
+    }
+
+    /// 下载 `file_path` 指向的文件到 `dest`。内容先写入 `dest` 旁的 `.part` 文件，
+    /// 中断后再次下载时用 Range 请求从已下载的位置继续，完成后改名为 `dest`
+    pub async fn download_file(
+        &self,
+        file_path: &str,
+        dest: &Path,
+        on_progress: Progress,
+    ) -> ApiResult<()> {
+        let partial = partial_path(dest);
+        let offset = tokio::fs::metadata(&partial)
+            .await
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+        println!(
+            "[DEBUG] Attempting to download {} from byte {}",
+            file_path, offset
+        );
+        let result = self
+            .send_authorized_streaming(|token| {
+                self.client
+                    .get(format!("{}/api/download", self.base_url))
+                    .query(&[("path", file_path)])
+                    .header("Authorization", format!("Bearer {}", token))
+                    .header(reqwest::header::RANGE, format!("bytes={}-", offset))
+                    .timeout(self.config.transfer_timeout)
+            })
+            .await;
+        let response = match result {
+            Ok(response) => response,
+            // 上次已经下载完整，只是没来得及改名
+            Err(ApiError::Server { code: 416, .. }) if offset > 0 => {
+                tokio::fs::rename(&partial, dest).await?;
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        // 服务端不支持 Range 时返回完整内容，从头开始写
+        let resumed = response.status() == StatusCode::PARTIAL_CONTENT;
+        let mut received = if resumed { offset } else { 0 };
+        let total = response
+            .content_length()
+            .map(|length| length + received)
+            .unwrap_or(0);
+        if let Some(parent) = dest.parent() {
+            tokio::fs::create_dir_all(parent).await?;
         }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(&partial)
+            .await?;
+        on_progress(received, total);
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            file.write_all(&chunk).await?;
+            received += chunk.len() as u64;
+            on_progress(received, total);
+        }
+        file.flush().await?;
+        drop(file);
+        tokio::fs::rename(&partial, dest).await?;
+        println!(
+            "[DEBUG] Downloaded {} bytes to {}",
+            received,
+            dest.display()
+        );
+        Ok(())
     }
 
-    pub fn get_chat_history(
+    /// 按游标分页获取聊天记录：`before_id` 为空时取最新一页，否则取ID小于它的消息。
+    /// `is_group` 为 true 时 `chat_id` 是群ID。返回的消息按ID从旧到新排列
+    pub async fn get_chat_history(
         &self,
         chat_id: i64,
+        is_group: bool,
         user_id: i64,
-    ) -> anyhow::Result<Vec<MessageResponse>> {
-        let token = self.get_token().unwrap_or_default();
+        before_id: Option<i64>,
+        limit: u32,
+    ) -> ApiResult<HistoryPage> {
+        println!("[DEBUG] Attempting to get chat history");
         println!(
-            "[DEBUG] Attempting to get chat history with token: {}",
-            token
+            "[DEBUG] Chat ID: {}, group: {}, User ID: {}, before: {:?}, limit: {}",
+            chat_id, is_group, user_id, before_id, limit
         );
-        println!("[DEBUG] Chat ID: {}, User ID: {}", chat_id, user_id);
-        let response = self
-            .client
-            .get(format!("{}/api/messages/{}", self.base_url, chat_id))
-            .header("Authorization", format!("Bearer {}", token))
-            .send()?;
-
-        let status = response.status();
-        println!("[DEBUG] Chat history response status: {}", status);
-        let response_text = response.text()?;
+        let mut query = vec![("limit", limit.to_string())];
+        if let Some(before_id) = before_id {
+            query.push(("before_id", before_id.to_string()));
+        }
+        let url = if is_group {
+            format!("{}/api/groups/{}/messages", self.base_url, chat_id)
+        } else {
+            format!("{}/api/messages/{}", self.base_url, chat_id)
+        };
+        let response_text = self
+            .send_authorized(|token| {
+                self.client
+                    .get(&url)
+                    .query(&query)
+                    .header("Authorization", format!("Bearer {}", token))
+            })
+            .await?;
         println!("[DEBUG] Chat history response body: {}", response_text);
 
-        if status.is_success() {
-            let response = serde_json::from_str::<Vec<MessageResponse>>(&response_text)?;
-            println!(
-                "[DEBUG] Successfully got {} chat history items",
-                response.len()
-            );
-            Ok(response)
-        } else {
-            let error = serde_json::from_str::<ErrorResponse>(&response_text)?;
-            Err(anyhow::anyhow!(
-                "Server error: {} - {}",
-                error.error.reason,
-                error.error.description
-            ))
+        let mut messages = decode::<Vec<MessageResponse>>(&response_text)?;
+        println!(
+            "[DEBUG] Successfully got {} chat history items",
+            messages.len()
+        );
+        messages.sort_by_key(|message| message.id);
+        Ok(HistoryPage {
+            has_more: messages.len() >= limit as usize,
+            messages,
+        })
+    }
+
+    /// 获取 `since_id` 之后的新消息，从最新一页往前翻直到翻到 `since_id`，最多翻 `max_pages` 页。
+    /// 翻完仍没到达 `since_id` 时 `has_more` 为 true，表示与本地缓存之间有缺口；
+    /// `since_id` 为空时只取最新一页，`has_more` 表示是否还有更早的消息
+    pub async fn get_messages_since(
+        &self,
+        chat_id: i64,
+        is_group: bool,
+        user_id: i64,
+        since_id: Option<i64>,
+        max_pages: usize,
+    ) -> ApiResult<HistoryPage> {
+        let mut messages: Vec<MessageResponse> = Vec::new();
+        let mut before_id = None;
+        for _ in 0..max_pages {
+            let page = self
+                .get_chat_history(chat_id, is_group, user_id, before_id, HISTORY_PAGE_SIZE)
+                .await?;
+            let reached = match (since_id, page.messages.first()) {
+                (Some(since_id), Some(first)) => first.id <= since_id,
+                _ => true,
+            };
+            before_id = page.messages.first().map(|message| message.id);
+            let mut older = page.messages;
+            older.retain(|message| since_id.is_none_or(|since_id| message.id > since_id));
+            older.append(&mut messages);
+            messages = older;
+            if reached || !page.has_more {
+                return Ok(HistoryPage {
+                    messages,
+                    has_more: since_id.is_none() && page.has_more,
+                });
+            }
         }
+        Ok(HistoryPage {
+            messages,
+            has_more: true,
+        })
     }
 }
+
+/// 下载中的文件：`dest` 加上 `.part` 后缀
+pub fn partial_path(dest: &Path) -> PathBuf {
+    let mut name = dest.as_os_str().to_os_string();
+    name.push(".part");
+    PathBuf::from(name)
+}
+
+/// 按块读取文件作为请求体，第一次读取时才打开文件，读取出错后结束
+fn file_body(path: PathBuf, total: u64, on_progress: Progress) -> reqwest::Body {
+    let stream = futures_util::stream::unfold(Some((None, 0u64)), move |state| {
+        let path = path.clone();
+        let on_progress = on_progress.clone();
+        async move {
+            let (file, sent): (Option<tokio::fs::File>, u64) = state?;
+            let mut file = match file {
+                Some(file) => file,
+                None => match tokio::fs::File::open(&path).await {
+                    Ok(file) => file,
+                    Err(e) => return Some((Err(e), None)),
+                },
+            };
+            let mut buffer = vec![0; UPLOAD_CHUNK_SIZE];
+            match file.read(&mut buffer).await {
+                Ok(0) => None,
+                Ok(read) => {
+                    buffer.truncate(read);
+                    let sent = sent + read as u64;
+                    on_progress(sent, total);
+                    Some((Ok(buffer), Some((Some(file), sent))))
+                }
+                Err(e) => Some((Err(e), None)),
+            }
+        }
+    });
+    reqwest::Body::wrap_stream(stream)
+}
+
+fn decode<T: serde::de::DeserializeOwned>(body: &str) -> ApiResult<T> {
+    serde_json::from_str(body).map_err(|e| {
+        println!("[DEBUG] Failed to decode response: {}", e);
+        ApiError::decode(body)
+    })
+}
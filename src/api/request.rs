@@ -0,0 +1,94 @@
+use std::cell::RefCell;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+use tokio::task::AbortHandle;
+
+/// 在后台运行的网络请求，可以随时取消。取消后不会再回调界面
+pub struct Request {
+    abort: AbortHandle,
+    /// 结果可能已经排队等待界面线程处理，取消时还要阻止回调
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Request {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.abort.abort();
+    }
+}
+
+/// 在 tokio 运行时上执行请求，完成后通过 `slint::invoke_from_event_loop` 回到界面线程调用 `on_done`
+pub fn spawn<T, Fut, F>(rt: &Runtime, request: Fut, on_done: F) -> Request
+where
+    Fut: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+    F: FnOnce(T) + Send + 'static,
+{
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let cancelled_for_task = cancelled.clone();
+    let task = rt.spawn(async move {
+        let result = request.await;
+        let deliver = move || {
+            if !cancelled_for_task.load(Ordering::SeqCst) {
+                on_done(result);
+            }
+        };
+        if let Err(e) = slint::invoke_from_event_loop(deliver) {
+            println!("[错误] 无法把请求结果交给界面线程: {}", e);
+        }
+    });
+    Request {
+        abort: task.abort_handle(),
+        cancelled,
+    }
+}
+
+/// 与 `spawn` 相同，但 `on_done` 可以持有只能在界面线程使用的状态（如 `Rc`、界面模型）。
+/// 必须在界面线程调用
+pub fn spawn_local<T, Fut, F>(rt: &Runtime, request: Fut, on_done: F) -> Request
+where
+    Fut: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+    F: FnOnce(T) + 'static,
+{
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let cancelled_for_task = cancelled.clone();
+    let task = rt.spawn(request);
+    let abort = task.abort_handle();
+    let result = slint::spawn_local(async move {
+        match task.await {
+            Ok(_) if cancelled_for_task.load(Ordering::SeqCst) => {}
+            Ok(result) => on_done(result),
+            Err(e) if e.is_cancelled() => println!("[调试] 请求已取消"),
+            Err(e) => println!("[错误] 请求任务异常: {}", e),
+        }
+    });
+    if let Err(e) = result {
+        println!("[错误] 启动请求失败: {}", e);
+        abort.abort();
+    }
+    Request { abort, cancelled }
+}
+
+/// 同一用途同一时间只保留一个请求：发起新请求时取消上一个，
+/// 例如切换会话后不再需要上一个会话的加载结果
+#[derive(Default)]
+pub struct RequestSlot {
+    current: RefCell<Option<Request>>,
+}
+
+impl RequestSlot {
+    pub fn replace(&self, request: Request) {
+        if let Some(previous) = self.current.replace(Some(request)) {
+            previous.cancel();
+        }
+    }
+
+    pub fn cancel(&self) {
+        if let Some(request) = self.current.take() {
+            request.cancel();
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Deserialize)]
+struct Claims {
+    exp: Option<i64>,
+}
+
+/// 读取 JWT 中的过期时间 `exp`（Unix 时间戳，秒）。不校验签名，只用于判断何时刷新
+pub fn expires_at(token: &str) -> Option<i64> {
+    let payload = token.split('.').nth(1)?;
+    // 部分服务端会保留 base64 的填充字符
+    let payload = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+    serde_json::from_slice::<Claims>(&payload).ok()?.exp
+}
+
+/// 距离需要刷新（过期前 `margin`）还有多久；已经到了刷新时间时为零。
+/// token 不是 JWT 或没有 `exp` 时返回 `None`
+pub fn refresh_delay(token: &str, margin: Duration) -> Option<Duration> {
+    let expires_at = expires_at(token)?;
+    let refresh_at = expires_at - margin.as_secs() as i64;
+    let now = chrono::Local::now().timestamp();
+    Some(Duration::from_secs(
+        refresh_at.saturating_sub(now).max(0) as u64
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jwt(claims: &str) -> String {
+        format!(
+            "{}.{}.signature",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#),
+            URL_SAFE_NO_PAD.encode(claims)
+        )
+    }
+
+    fn now() -> i64 {
+        chrono::Local::now().timestamp()
+    }
+
+    #[test]
+    fn reads_exp_claim() {
+        assert_eq!(
+            expires_at(&jwt(r#"{"sub":"1","exp":1700000000}"#)),
+            Some(1700000000)
+        );
+    }
+
+    #[test]
+    fn accepts_padded_payload() {
+        // 长度不是3的倍数的内容编码后带填充
+        let payload = base64::engine::general_purpose::URL_SAFE.encode(r#"{"exp": 1700000000}"#);
+        assert!(payload.ends_with('='));
+        let token = format!("header.{}.signature", payload);
+        assert_eq!(expires_at(&token), Some(1700000000));
+    }
+
+    #[test]
+    fn not_a_jwt_has_no_expiry() {
+        assert_eq!(expires_at("opaque-token"), None);
+        assert_eq!(expires_at("a.!!!.c"), None);
+        assert_eq!(expires_at(&jwt(r#"{"sub":"1"}"#)), None);
+        assert_eq!(refresh_delay("opaque-token", Duration::from_secs(60)), None);
+    }
+
+    #[test]
+    fn refreshes_margin_before_expiry() {
+        let token = jwt(&format!(r#"{{"exp":{}}}"#, now() + 3600));
+        let delay = refresh_delay(&token, Duration::from_secs(600)).unwrap();
+        // 计算期间时间可能走过一秒
+        assert!(delay <= Duration::from_secs(3000));
+        assert!(delay >= Duration::from_secs(2998));
+    }
+
+    #[test]
+    fn refreshes_now_inside_margin_or_after_expiry() {
+        let margin = Duration::from_secs(600);
+        let soon = jwt(&format!(r#"{{"exp":{}}}"#, now() + 300));
+        assert_eq!(refresh_delay(&soon, margin), Some(Duration::ZERO));
+        let expired = jwt(&format!(r#"{{"exp":{}}}"#, now() - 3600));
+        assert_eq!(refresh_delay(&expired, margin), Some(Duration::ZERO));
+    }
+}
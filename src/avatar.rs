@@ -0,0 +1,239 @@
+use crate::api::request;
+use crate::api::NetworkClient;
+use crate::config::{self, MediaConfig};
+use crate::media::{self, DiskCache};
+use crate::{Main, Store};
+use anyhow::Result;
+use image::ImageFormat;
+use slint::{ComponentHandle, Image, Model, Rgb8Pixel, Rgba8Pixel, SharedPixelBuffer};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+use std::rc::Rc;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+
+/// 占位头像的底色，按用户ID选取
+const PLACEHOLDER_COLORS: [[u8; 3]; 8] = [
+    [87, 160, 230],
+    [7, 193, 96],
+    [230, 162, 60],
+    [150, 120, 210],
+    [240, 110, 110],
+    [60, 180, 190],
+    [200, 130, 80],
+    [120, 140, 160],
+];
+
+/// 没有头像时显示的首字
+pub fn initial(name: &str) -> String {
+    name.chars()
+        .next()
+        .map(|first| first.to_uppercase().to_string())
+        .unwrap_or_default()
+}
+
+/// 界面上的头像。没有加载到头像时 `image` 是纯色底图，由界面在上面绘制 `initial`
+pub struct Avatar {
+    pub image: Image,
+    pub initial: slint::SharedString,
+}
+
+/// 已加载的头像和它的地址，地址变化时重新加载
+struct Loaded {
+    url: String,
+    image: Image,
+}
+
+/// 头像服务：按服务端提供的地址下载头像，在后台缩放到显示尺寸，
+/// 缓存在内存和磁盘中；没有头像的用户显示按用户ID着色的首字占位头像
+pub struct Avatars {
+    weak_main: slint::Weak<Main>,
+    network: Arc<NetworkClient>,
+    rt: Arc<Runtime>,
+    config: MediaConfig,
+    /// 缩放后的 PNG，按头像地址
+    disk: Arc<DiskCache>,
+    /// 已加载的头像，按用户ID
+    loaded: RefCell<HashMap<i64, Loaded>>,
+    loading: RefCell<HashSet<(i64, String)>>,
+    /// 各颜色的占位底图，同色的用户共用
+    placeholders: RefCell<HashMap<usize, Image>>,
+}
+
+impl Avatars {
+    pub fn new(
+        weak_main: slint::Weak<Main>,
+        user_id: i64,
+        network: Arc<NetworkClient>,
+        rt: Arc<Runtime>,
+    ) -> Self {
+        let config = MediaConfig::default();
+        let disk = DiskCache::new(
+            config::account_data_dir(user_id).join("avatars"),
+            config.avatar_cache_bytes,
+        );
+        Self {
+            weak_main,
+            network,
+            rt,
+            config,
+            disk: Arc::new(disk),
+            loaded: RefCell::new(HashMap::new()),
+            loading: RefCell::new(HashSet::new()),
+            placeholders: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// 用户的头像，还没有加载到时返回占位头像
+    pub fn get(&self, user_id: i64, name: &str) -> Avatar {
+        if let Some(loaded) = self.loaded.borrow().get(&user_id) {
+            return Avatar {
+                image: loaded.image.clone(),
+                initial: Default::default(),
+            };
+        }
+        Avatar {
+            image: self.placeholder(user_id),
+            initial: initial(name).into(),
+        }
+    }
+
+    fn placeholder(&self, user_id: i64) -> Image {
+        let index = user_id.rem_euclid(PLACEHOLDER_COLORS.len() as i64) as usize;
+        self.placeholders
+            .borrow_mut()
+            .entry(index)
+            .or_insert_with(|| {
+                let [r, g, b] = PLACEHOLDER_COLORS[index];
+                // 纯色底图只需要很小的尺寸，显示时拉伸
+                let mut buffer = SharedPixelBuffer::<Rgb8Pixel>::new(4, 4);
+                buffer.make_mut_slice().fill(Rgb8Pixel { r, g, b });
+                Image::from_rgb8(buffer)
+            })
+            .clone()
+    }
+
+    /// 记录用户的头像地址，与已加载的不同时在后台加载。没有地址的用户保持占位头像
+    pub fn update(self: &Rc<Self>, user_id: i64, url: Option<&str>) {
+        let Some(url) = url.filter(|url| !url.is_empty()) else {
+            return;
+        };
+        if self
+            .loaded
+            .borrow()
+            .get(&user_id)
+            .is_some_and(|loaded| loaded.url == url)
+        {
+            return;
+        }
+        if self.loading.borrow_mut().insert((user_id, url.to_string())) {
+            self.load(user_id, url.to_string());
+        }
+    }
+
+    /// 从服务端获取用户资料并加载头像，用于自己等不在好友列表中的用户
+    pub fn refresh_user(self: &Rc<Self>, user_id: i64) {
+        let network = self.network.clone();
+        let avatars = Rc::downgrade(self);
+        let _ = request::spawn_local(
+            &self.rt,
+            async move { network.get_user(user_id).await },
+            move |result| match result {
+                Ok(profile) => {
+                    if let Some(avatars) = avatars.upgrade() {
+                        avatars.update(profile.id, profile.avatar_url.as_deref());
+                    }
+                }
+                Err(e) => println!("[错误] 获取用户资料失败: {}", e),
+            },
+        );
+    }
+
+    fn load(self: &Rc<Self>, user_id: i64, url: String) {
+        let network = self.network.clone();
+        let disk = self.disk.clone();
+        let size = self.config.avatar_size;
+        let key = url.clone();
+        let avatars = Rc::downgrade(self);
+        let _ = request::spawn_local(
+            &self.rt,
+            async move {
+                let cached = disk.get(&key);
+                let content = match &cached {
+                    Some(path) => tokio::fs::read(path).await?,
+                    None => network.fetch_avatar(&key).await?,
+                };
+                tokio::task::spawn_blocking(move || {
+                    let image = image::load_from_memory(&content)?;
+                    if cached.is_some() {
+                        return Ok(media::to_pixel_buffer(&image));
+                    }
+                    let image =
+                        image.resize_to_fill(size, size, image::imageops::FilterType::Triangle);
+                    let mut png = Vec::new();
+                    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+                    disk.insert(&key, &png)?;
+                    Ok(media::to_pixel_buffer(&image))
+                })
+                .await?
+            },
+            move |result: Result<SharedPixelBuffer<Rgba8Pixel>>| {
+                let Some(avatars) = avatars.upgrade() else {
+                    return;
+                };
+                avatars.loading.borrow_mut().remove(&(user_id, url.clone()));
+                match result {
+                    Ok(pixels) => {
+                        let image = Image::from_rgba8(pixels);
+                        avatars.loaded.borrow_mut().insert(
+                            user_id,
+                            Loaded {
+                                url,
+                                image: image.clone(),
+                            },
+                        );
+                        avatars.apply(user_id, &image);
+                    }
+                    Err(e) => println!("[错误] 加载用户 {} 的头像失败: {}", user_id, e),
+                }
+            },
+        );
+    }
+
+    /// 把加载好的头像换到界面上这个用户出现的地方
+    fn apply(&self, user_id: i64, image: &Image) {
+        let Some(window) = self.weak_main.upgrade() else {
+            return;
+        };
+        let store = window.global::<Store>();
+        let mut user_info = store.get_user_info();
+        if user_info.id as i64 == user_id {
+            user_info.avatar = image.clone();
+            user_info.avatar_initial = Default::default();
+            store.set_user_info(user_info);
+        }
+        let chat_items = store.get_chat_items();
+        for index in 0..chat_items.row_count() {
+            let Some(mut chat) = chat_items.row_data(index) else {
+                continue;
+            };
+            if !chat.is_group && chat.id as i64 == user_id {
+                chat.avatar = image.clone();
+                chat.avatar_initial = Default::default();
+                chat_items.set_row_data(index, chat);
+            }
+        }
+        let message_items = store.get_message_items();
+        for index in 0..message_items.row_count() {
+            let Some(mut item) = message_items.row_data(index) else {
+                continue;
+            };
+            if item.sender_id as i64 == user_id {
+                item.avatar = image.clone();
+                item.avatar_initial = Default::default();
+                message_items.set_row_data(index, item);
+            }
+        }
+    }
+}
@@ -0,0 +1,646 @@
+use crate::api::{FriendInfo, GroupInfo, GroupMember, MessageResponse};
+use crate::conversation::ConversationKey;
+use crate::search;
+use crate::websocket::ChatMessage;
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// 数据库结构版本，修改表结构时递增并在 `migrate` 中补上升级语句
+const SCHEMA_VERSION: i32 = 1;
+
+/// 会话在聊天列表中的摘要
+#[derive(Debug, Clone)]
+pub struct ConversationSummary {
+    pub key: ConversationKey,
+    pub preview: String,
+    pub message_type: String,
+    pub last_timestamp: i64,
+}
+
+/// 搜索条件，为 `None` 的条件不限制
+#[derive(Debug, Clone)]
+pub struct SearchFilter {
+    /// 由 `search::fts_query` 生成的全文检索表达式
+    pub query: String,
+    pub sender_id: Option<i64>,
+    pub conversation: Option<ConversationKey>,
+    /// 只搜索这个时间之后的消息
+    pub since: Option<i64>,
+    pub message_type: Option<String>,
+    pub limit: u32,
+}
+
+/// 本地消息缓存，每个账号一个 SQLite 数据库，保存好友、群、会话、消息和同步位置，
+/// 界面先从缓存显示，服务器不可达时也能浏览历史消息
+pub struct MessageCache {
+    conn: Mutex<Connection>,
+}
+
+impl MessageCache {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path)?;
+        println!("[调试] 已打开本地缓存: {:?}", path);
+        Self::with_connection(conn)
+    }
+
+    /// 无法打开磁盘上的数据库时退回内存数据库，本次运行仍可正常使用
+    pub fn in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(mut conn: Connection) -> Result<Self> {
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        migrate(&mut conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub fn save_friends(&self, friends: &[FriendInfo]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM friends", [])?;
+        for friend in friends {
+            tx.execute(
+                "INSERT INTO friends (id, username, avatar_url) VALUES (?1, ?2, ?3)",
+                params![friend.id, friend.username, friend.avatar_url],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn friends(&self) -> Result<Vec<FriendInfo>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT id, username, avatar_url FROM friends ORDER BY rowid")?;
+        let friends = stmt
+            .query_map([], |row| {
+                Ok(FriendInfo {
+                    id: row.get(0)?,
+                    username: row.get(1)?,
+                    avatar_url: row.get(2)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(friends)
+    }
+
+    /// 保存我加入的群，成员以 JSON 保存
+    pub fn save_groups(&self, groups: &[GroupInfo]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM groups", [])?;
+        for group in groups {
+            tx.execute(
+                "INSERT INTO groups (id, name, owner_id, members) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    group.id,
+                    group.name,
+                    group.owner_id,
+                    serde_json::to_string(&group.members)?
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn groups(&self) -> Result<Vec<GroupInfo>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT id, name, owner_id, members FROM groups ORDER BY rowid")?;
+        let groups = stmt
+            .query_map([], |row| {
+                let members: String = row.get(3)?;
+                Ok(GroupInfo {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    owner_id: row.get(2)?,
+                    members: serde_json::from_str::<Vec<GroupMember>>(&members).unwrap_or_default(),
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(groups)
+    }
+
+    /// 保存一批消息。`replace_older` 为 true 时说明这批消息与缓存之间可能有缺口，
+    /// 先删除比它们更早的缓存，避免翻页时跳过缺失的消息
+    pub fn save_messages(
+        &self,
+        key: ConversationKey,
+        messages: &[MessageResponse],
+        replace_older: bool,
+    ) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        if replace_older {
+            if let Some(oldest) = messages.iter().map(|message| message.id).min() {
+                tx.execute(
+                    "DELETE FROM messages_fts WHERE rowid IN (
+                        SELECT id FROM messages WHERE chat_id = ?1 AND is_group = ?2 AND id < ?3
+                    )",
+                    params![key.id, key.is_group, oldest],
+                )?;
+                tx.execute(
+                    "DELETE FROM messages WHERE chat_id = ?1 AND is_group = ?2 AND id < ?3",
+                    params![key.id, key.is_group, oldest],
+                )?;
+            }
+        }
+        for message in messages {
+            insert_message(&tx, key, message)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// 保存一条实时收发的消息。消息ID是表和全文索引的主键，没有服务端ID的消息不缓存：
+    /// 自己发出的在确认后保存，收到的由下次增量同步带着ID补上（收到消息不推进同步位置）
+    pub fn save_chat_message(&self, key: ConversationKey, message: &ChatMessage) -> Result<()> {
+        let Some(id) = message.message_id else {
+            println!(
+                "[调试] 消息没有服务端ID，暂不缓存，等待增量同步: {:?}",
+                message.client_id
+            );
+            return Ok(());
+        };
+        let row = MessageResponse {
+            id,
+            sender_id: message.sender_id,
+            receiver_id: (!key.is_group).then_some(message.receiver_id),
+            group_id: key.is_group.then_some(message.receiver_id),
+            content: message.content.clone(),
+            timestamp: message.timestamp,
+            direction: message.direction.clone(),
+            username: message.username.clone(),
+            file_path: message.file_path.clone(),
+            file_name: message.file_name.clone(),
+            file_size: message.file_size,
+            message_type: Some(message.message_type.clone()),
+        };
+        let conn = self.conn.lock().unwrap();
+        insert_message(&conn, key, &row)
+    }
+
+    /// 读取会话中ID小于 `before_id` 的最近 `limit` 条消息，按ID从旧到新排列
+    pub fn messages(
+        &self,
+        key: ConversationKey,
+        before_id: Option<i64>,
+        limit: u32,
+    ) -> Result<Vec<MessageResponse>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, sender_id, receiver_id, group_id, content, timestamp, direction,
+                    username, file_path, file_name, file_size, message_type
+             FROM messages
+             WHERE chat_id = ?1 AND is_group = ?2 AND id < ?3
+             ORDER BY id DESC
+             LIMIT ?4",
+        )?;
+        let mut messages = stmt
+            .query_map(
+                params![key.id, key.is_group, before_id.unwrap_or(i64::MAX), limit],
+                message_from_row,
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        messages.reverse();
+        Ok(messages)
+    }
+
+    /// 读取会话中ID不小于 `from_id` 的所有消息，按ID从旧到新排列，用于从搜索结果定位消息
+    pub fn messages_from(
+        &self,
+        key: ConversationKey,
+        from_id: i64,
+    ) -> Result<Vec<MessageResponse>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, sender_id, receiver_id, group_id, content, timestamp, direction,
+                    username, file_path, file_name, file_size, message_type
+             FROM messages
+             WHERE chat_id = ?1 AND is_group = ?2 AND id >= ?3
+             ORDER BY id",
+        )?;
+        let messages = stmt
+            .query_map(params![key.id, key.is_group, from_id], message_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(messages)
+    }
+
+    /// 全文搜索缓存的消息，最新的排在前面
+    pub fn search(&self, filter: &SearchFilter) -> Result<Vec<(ConversationKey, MessageResponse)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT m.id, m.sender_id, m.receiver_id, m.group_id, m.content, m.timestamp,
+                    m.direction, m.username, m.file_path, m.file_name, m.file_size,
+                    m.message_type, m.chat_id, m.is_group
+             FROM messages_fts
+             JOIN messages m ON m.id = messages_fts.rowid
+             WHERE messages_fts MATCH ?1
+               AND (?2 IS NULL OR m.sender_id = ?2)
+               AND (?3 IS NULL OR (m.chat_id = ?3 AND m.is_group = ?4))
+               AND (?5 IS NULL OR m.timestamp >= ?5)
+               AND (?6 IS NULL OR COALESCE(m.message_type, 'text') = ?6)
+             ORDER BY m.timestamp DESC, m.id DESC
+             LIMIT ?7",
+        )?;
+        let results = stmt
+            .query_map(
+                params![
+                    filter.query,
+                    filter.sender_id,
+                    filter.conversation.map(|key| key.id),
+                    filter.conversation.map(|key| key.is_group),
+                    filter.since,
+                    filter.message_type,
+                    filter.limit,
+                ],
+                |row| {
+                    let key = ConversationKey {
+                        id: row.get(12)?,
+                        is_group: row.get(13)?,
+                    };
+                    Ok((key, message_from_row(row)?))
+                },
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(results)
+    }
+
+    /// 会话已经与服务端同步到的消息ID
+    pub fn sync_cursor(&self, key: ConversationKey) -> Result<Option<i64>> {
+        let conn = self.conn.lock().unwrap();
+        let cursor = conn
+            .query_row(
+                "SELECT last_id FROM sync_cursors WHERE chat_id = ?1 AND is_group = ?2",
+                params![key.id, key.is_group],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(cursor)
+    }
+
+    pub fn set_sync_cursor(&self, key: ConversationKey, last_id: i64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO sync_cursors (chat_id, is_group, last_id) VALUES (?1, ?2, ?3)
+             ON CONFLICT (chat_id, is_group) DO UPDATE SET last_id = MAX(last_id, excluded.last_id)",
+            params![key.id, key.is_group, last_id],
+        )?;
+        Ok(())
+    }
+
+    /// 记录会话的最后一条消息，用于下次启动时恢复聊天列表的预览和顺序
+    pub fn touch_conversation(&self, key: ConversationKey, message: &ChatMessage) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO conversations (chat_id, is_group, preview, message_type, last_timestamp)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (chat_id, is_group) DO UPDATE SET
+                preview = excluded.preview,
+                message_type = excluded.message_type,
+                last_timestamp = excluded.last_timestamp
+             WHERE excluded.last_timestamp >= conversations.last_timestamp",
+            params![
+                key.id,
+                key.is_group,
+                message.content,
+                message.message_type,
+                message.timestamp
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// 所有会话的摘要，最近有消息的排在前面
+    pub fn conversations(&self) -> Result<Vec<ConversationSummary>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT chat_id, is_group, preview, message_type, last_timestamp
+             FROM conversations ORDER BY last_timestamp DESC",
+        )?;
+        let summaries = stmt
+            .query_map([], |row| {
+                Ok(ConversationSummary {
+                    key: ConversationKey {
+                        id: row.get(0)?,
+                        is_group: row.get(1)?,
+                    },
+                    preview: row.get(2)?,
+                    message_type: row.get(3)?,
+                    last_timestamp: row.get(4)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(summaries)
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let version: i32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version >= SCHEMA_VERSION {
+        return Ok(());
+    }
+    // 升级中途失败时整体回滚，下次启动从原来的版本重新升级
+    let tx = conn.transaction()?;
+    if version < 1 {
+        tx.execute_batch(
+            "CREATE TABLE friends (
+                id INTEGER PRIMARY KEY,
+                username TEXT NOT NULL,
+                avatar_url TEXT
+            );
+            CREATE TABLE groups (
+                id INTEGER PRIMARY KEY,
+                name TEXT NOT NULL,
+                owner_id INTEGER NOT NULL,
+                members TEXT NOT NULL
+            );
+            CREATE TABLE messages (
+                id INTEGER PRIMARY KEY,
+                chat_id INTEGER NOT NULL,
+                is_group INTEGER NOT NULL,
+                sender_id INTEGER NOT NULL,
+                receiver_id INTEGER,
+                group_id INTEGER,
+                content TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                direction TEXT NOT NULL,
+                username TEXT NOT NULL,
+                file_path TEXT,
+                file_name TEXT,
+                file_size INTEGER,
+                message_type TEXT
+            );
+            CREATE INDEX idx_messages_chat ON messages (chat_id, is_group, id);
+            -- 全文索引：rowid 与消息ID一致，内容是 `search::segment` 切分后的文本
+            CREATE VIRTUAL TABLE messages_fts USING fts5(body);
+            CREATE TABLE conversations (
+                chat_id INTEGER NOT NULL,
+                is_group INTEGER NOT NULL,
+                preview TEXT NOT NULL,
+                message_type TEXT NOT NULL,
+                last_timestamp INTEGER NOT NULL,
+                PRIMARY KEY (chat_id, is_group)
+            );
+            CREATE TABLE sync_cursors (
+                chat_id INTEGER NOT NULL,
+                is_group INTEGER NOT NULL,
+                last_id INTEGER NOT NULL,
+                PRIMARY KEY (chat_id, is_group)
+            );",
+        )?;
+    }
+    tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn insert_message(
+    conn: &Connection,
+    key: ConversationKey,
+    message: &MessageResponse,
+) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO messages (
+            id, chat_id, is_group, sender_id, receiver_id, group_id, content, timestamp,
+            direction, username, file_path, file_name, file_size, message_type
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        params![
+            message.id,
+            key.id,
+            key.is_group,
+            message.sender_id,
+            message.receiver_id,
+            message.group_id,
+            message.content,
+            message.timestamp,
+            message.direction,
+            message.username,
+            message.file_path,
+            message.file_name,
+            message.file_size,
+            message.message_type,
+        ],
+    )?;
+    index_message(conn, message.id, &message.content)
+}
+
+fn index_message(conn: &Connection, id: i64, content: &str) -> Result<()> {
+    conn.execute("DELETE FROM messages_fts WHERE rowid = ?1", params![id])?;
+    conn.execute(
+        "INSERT INTO messages_fts (rowid, body) VALUES (?1, ?2)",
+        params![id, search::segment(content)],
+    )?;
+    Ok(())
+}
+
+fn message_from_row(row: &Row) -> rusqlite::Result<MessageResponse> {
+    Ok(MessageResponse {
+        id: row.get(0)?,
+        sender_id: row.get(1)?,
+        receiver_id: row.get(2)?,
+        group_id: row.get(3)?,
+        content: row.get(4)?,
+        timestamp: row.get(5)?,
+        direction: row.get(6)?,
+        username: row.get(7)?,
+        file_path: row.get(8)?,
+        file_name: row.get(9)?,
+        file_size: row.get(10)?,
+        message_type: row.get(11)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: i64, sender_id: i64, content: &str, timestamp: i64) -> MessageResponse {
+        MessageResponse {
+            id,
+            sender_id,
+            receiver_id: Some(if sender_id == 1 { 2 } else { 1 }),
+            group_id: None,
+            content: content.to_string(),
+            timestamp,
+            direction: "send".to_string(),
+            username: format!("user{}", sender_id),
+            file_path: None,
+            file_name: None,
+            file_size: None,
+            message_type: Some("text".to_string()),
+        }
+    }
+
+    fn filter(text: &str) -> SearchFilter {
+        SearchFilter {
+            query: search::fts_query(text).unwrap(),
+            sender_id: None,
+            conversation: None,
+            since: None,
+            message_type: None,
+            limit: 50,
+        }
+    }
+
+    fn ids(results: &[(ConversationKey, MessageResponse)]) -> Vec<i64> {
+        results.iter().map(|(_, message)| message.id).collect()
+    }
+
+    #[test]
+    fn new_database_gets_current_schema() {
+        let cache = MessageCache::in_memory().unwrap();
+        let mut conn = cache.conn.lock().unwrap();
+        let version: i32 = conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+        for table in [
+            "friends",
+            "groups",
+            "messages",
+            "messages_fts",
+            "conversations",
+            "sync_cursors",
+        ] {
+            let exists: bool = conn
+                .query_row(
+                    "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = ?1)",
+                    params![table],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert!(exists, "缺少表 {}", table);
+        }
+        // 已是最新版本时不再执行建表语句
+        migrate(&mut conn).unwrap();
+    }
+
+    #[test]
+    fn reopening_keeps_cached_data() {
+        let path = std::env::temp_dir().join(format!("me_chat_cache_{}.db", uuid::Uuid::new_v4()));
+        let key = ConversationKey::person(2);
+        MessageCache::open(&path)
+            .unwrap()
+            .save_messages(key, &[message(1, 1, "你好", 100)], false)
+            .unwrap();
+        let cache = MessageCache::open(&path).unwrap();
+        assert_eq!(cache.messages(key, None, 10).unwrap().len(), 1);
+        drop(cache);
+        for suffix in ["", "-wal", "-shm"] {
+            let mut name = path.as_os_str().to_os_string();
+            name.push(suffix);
+            let _ = std::fs::remove_file(name);
+        }
+    }
+
+    #[test]
+    fn pages_messages_oldest_first() {
+        let cache = MessageCache::in_memory().unwrap();
+        let key = ConversationKey::person(2);
+        let page: Vec<_> = (1..=5).map(|id| message(id, 1, "消息", id * 10)).collect();
+        cache.save_messages(key, &page, false).unwrap();
+
+        let latest = cache.messages(key, None, 2).unwrap();
+        assert_eq!(latest.iter().map(|m| m.id).collect::<Vec<_>>(), vec![4, 5]);
+        let older = cache.messages(key, Some(4), 2).unwrap();
+        assert_eq!(older.iter().map(|m| m.id).collect::<Vec<_>>(), vec![2, 3]);
+        assert!(cache
+            .messages(ConversationKey::group(2), None, 10)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn replacing_older_messages_drops_them_from_search() {
+        let cache = MessageCache::in_memory().unwrap();
+        let key = ConversationKey::person(2);
+        cache
+            .save_messages(key, &[message(1, 1, "旧的会议", 10)], false)
+            .unwrap();
+        cache
+            .save_messages(key, &[message(10, 1, "新的会议", 20)], true)
+            .unwrap();
+        assert_eq!(ids(&cache.search(&filter("会议")).unwrap()), vec![10]);
+        assert_eq!(cache.messages(key, None, 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn searches_cjk_and_words_newest_first() {
+        let cache = MessageCache::in_memory().unwrap();
+        let key = ConversationKey::person(2);
+        cache
+            .save_messages(
+                key,
+                &[
+                    message(1, 1, "明天开会", 100),
+                    message(2, 2, "明天的meeting取消了", 200),
+                    message(3, 2, "今天天气不错", 300),
+                ],
+                false,
+            )
+            .unwrap();
+        assert_eq!(ids(&cache.search(&filter("明天")).unwrap()), vec![2, 1]);
+        assert_eq!(ids(&cache.search(&filter("MEET")).unwrap()), vec![2]);
+        assert_eq!(ids(&cache.search(&filter("天气")).unwrap()), vec![3]);
+        assert!(cache.search(&filter("后天")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn search_applies_filters() {
+        let cache = MessageCache::in_memory().unwrap();
+        cache
+            .save_messages(
+                ConversationKey::person(2),
+                &[message(1, 1, "项目进度", 100)],
+                false,
+            )
+            .unwrap();
+        cache
+            .save_messages(
+                ConversationKey::person(3),
+                &[message(2, 3, "项目上线", 200)],
+                false,
+            )
+            .unwrap();
+
+        let by_sender = SearchFilter {
+            sender_id: Some(3),
+            ..filter("项目")
+        };
+        assert_eq!(ids(&cache.search(&by_sender).unwrap()), vec![2]);
+        let by_conversation = SearchFilter {
+            conversation: Some(ConversationKey::person(2)),
+            ..filter("项目")
+        };
+        assert_eq!(ids(&cache.search(&by_conversation).unwrap()), vec![1]);
+        let since = SearchFilter {
+            since: Some(150),
+            ..filter("项目")
+        };
+        assert_eq!(ids(&cache.search(&since).unwrap()), vec![2]);
+        let files_only = SearchFilter {
+            message_type: Some("file".to_string()),
+            ..filter("项目")
+        };
+        assert!(cache.search(&files_only).unwrap().is_empty());
+    }
+
+    #[test]
+    fn sync_cursor_only_moves_forward() {
+        let cache = MessageCache::in_memory().unwrap();
+        let key = ConversationKey::person(2);
+        assert_eq!(cache.sync_cursor(key).unwrap(), None);
+        cache.set_sync_cursor(key, 10).unwrap();
+        cache.set_sync_cursor(key, 5).unwrap();
+        assert_eq!(cache.sync_cursor(key).unwrap(), Some(10));
+    }
+}
@@ -1,3 +1,6 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
 pub struct WindowConfig {
     pub default_width: f32,
     pub default_height: f32,
@@ -11,3 +14,230 @@ impl Default for WindowConfig {
         }
     }
 }
+
+/// WebSocket 断线重连的退避参数
+#[derive(Debug, Clone)]
+pub struct ReconnectConfig {
+    /// 第一次重连前的等待时间
+    pub initial_delay: Duration,
+    /// 等待时间上限
+    pub max_delay: Duration,
+    /// 每次失败后等待时间的放大倍数
+    pub multiplier: f64,
+    /// 随机抖动比例（0.0 ~ 1.0），避免大量客户端同时重连
+    pub jitter: f64,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.3,
+        }
+    }
+}
+
+impl ReconnectConfig {
+    /// 计算第 `attempt` 次（从1开始）重连前的等待时间
+    pub fn delay_for(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31) as i32;
+        let base = self.initial_delay.as_secs_f64() * self.multiplier.powi(exponent);
+        let capped = base.min(self.max_delay.as_secs_f64());
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = 1.0 - jitter * rand::random::<f64>();
+        Duration::from_secs_f64(capped * factor)
+    }
+}
+
+/// WebSocket 心跳参数
+#[derive(Debug, Clone)]
+pub struct HeartbeatConfig {
+    /// 发送 Ping 的间隔
+    pub interval: Duration,
+    /// 超过该时间没有收到任何数据帧即认为连接已失效
+    pub timeout: Duration,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(15),
+            timeout: Duration::from_secs(45),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WebSocketConfig {
+    pub reconnect: ReconnectConfig,
+    pub heartbeat: HeartbeatConfig,
+    /// 消息发出后等待服务端确认的时间，超时后用同一个客户端消息ID重新发送
+    pub ack_timeout: Duration,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self {
+            reconnect: ReconnectConfig::default(),
+            heartbeat: HeartbeatConfig::default(),
+            ack_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// 在线状态参数
+#[derive(Debug, Clone)]
+pub struct PresenceConfig {
+    /// 多久没有窗口输入后自动切换为离开
+    pub idle_timeout: Duration,
+    /// 检查是否空闲的间隔
+    pub check_interval: Duration,
+}
+
+impl Default for PresenceConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(5 * 60),
+            check_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+/// 图片消息和头像参数
+#[derive(Debug, Clone)]
+pub struct MediaConfig {
+    /// 缩略图长边的像素数
+    pub thumbnail_size: u32,
+    /// 缩略图磁盘缓存的容量上限
+    pub thumbnail_cache_bytes: u64,
+    /// 查看大图时下载的原图缓存的容量上限
+    pub image_cache_bytes: u64,
+    /// 查看大图时长边最多保留的像素数，避免超大图片占用过多内存
+    pub viewer_max_size: u32,
+    /// 头像解码后的边长，界面上最大显示 56px，按两倍准备以适应高分屏
+    pub avatar_size: u32,
+    /// 头像磁盘缓存的容量上限
+    pub avatar_cache_bytes: u64,
+}
+
+impl Default for MediaConfig {
+    fn default() -> Self {
+        Self {
+            thumbnail_size: 240,
+            thumbnail_cache_bytes: 64 * 1024 * 1024,
+            image_cache_bytes: 256 * 1024 * 1024,
+            viewer_max_size: 4096,
+            avatar_size: 112,
+            avatar_cache_bytes: 16 * 1024 * 1024,
+        }
+    }
+}
+
+/// HTTP 接口请求参数
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    /// 建立连接的超时时间
+    pub connect_timeout: Duration,
+    /// 整个请求（含读取响应）的超时时间
+    pub request_timeout: Duration,
+    /// 被限流（429）后自动重试的次数
+    pub rate_limit_retries: u32,
+    /// 服务端没有给出 Retry-After 时的等待时间
+    pub default_retry_after: Duration,
+    /// 自动重试最多等待的时间，超过后直接把限流错误交给调用方
+    pub max_retry_after: Duration,
+    /// 在 token 过期前多久主动刷新
+    pub token_refresh_margin: Duration,
+    /// 主动刷新 token 失败（如服务器暂时不可达）后，多久再试一次
+    pub token_refresh_retry: Duration,
+    /// 上传、下载文件的超时时间，代替 `request_timeout`
+    pub transfer_timeout: Duration,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(15),
+            rate_limit_retries: 1,
+            default_retry_after: Duration::from_secs(2),
+            max_retry_after: Duration::from_secs(10),
+            token_refresh_margin: Duration::from_secs(60),
+            token_refresh_retry: Duration::from_secs(30),
+            transfer_timeout: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// 应用数据目录，例如 Linux 下的 `~/.local/share/me_chat`
+pub fn data_dir() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("me_chat")
+}
+
+/// 某个账号专属的数据目录
+pub fn account_data_dir(user_id: i64) -> PathBuf {
+    data_dir().join("accounts").join(user_id.to_string())
+}
+
+/// 默认的文件下载目录，例如 `~/Downloads/MeChat`
+pub fn default_download_dir() -> PathBuf {
+    dirs::download_dir().unwrap_or_else(data_dir).join("MeChat")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn without_jitter() -> ReconnectConfig {
+        ReconnectConfig {
+            jitter: 0.0,
+            ..ReconnectConfig::default()
+        }
+    }
+
+    #[test]
+    fn delay_grows_exponentially() {
+        let config = without_jitter();
+        assert_eq!(config.delay_for(1), Duration::from_secs(1));
+        assert_eq!(config.delay_for(2), Duration::from_secs(2));
+        assert_eq!(config.delay_for(3), Duration::from_secs(4));
+        assert_eq!(config.delay_for(5), Duration::from_secs(16));
+    }
+
+    #[test]
+    fn attempt_zero_uses_initial_delay() {
+        assert_eq!(without_jitter().delay_for(0), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn delay_is_capped() {
+        let config = without_jitter();
+        assert_eq!(config.delay_for(6), Duration::from_secs(30));
+        assert_eq!(config.delay_for(u32::MAX), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn jitter_only_shortens_delay() {
+        let config = ReconnectConfig::default();
+        for _ in 0..100 {
+            let delay = config.delay_for(4);
+            assert!(delay <= Duration::from_secs(8));
+            assert!(delay >= Duration::from_secs_f64(8.0 * 0.7));
+        }
+    }
+
+    #[test]
+    fn jitter_out_of_range_is_clamped() {
+        let config = ReconnectConfig {
+            jitter: 5.0,
+            ..ReconnectConfig::default()
+        };
+        for _ in 0..100 {
+            assert!(config.delay_for(2) <= Duration::from_secs(2));
+        }
+    }
+}
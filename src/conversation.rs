@@ -0,0 +1,792 @@
+use crate::api::request::{self, RequestSlot};
+use crate::api::{ApiError, HistoryPage, NetworkClient, HISTORY_PAGE_SIZE};
+use crate::avatar::Avatars;
+use crate::cache::MessageCache;
+use crate::media::Images;
+use crate::read_state::{self, ReadTracker};
+use crate::transfer::{self, Downloads};
+use crate::websocket::{new_message_id, ChatMessage, MessageStatus, OutboxEntry};
+use crate::{ChatItem, ConnectionStatus, Main, MessageItem, Store};
+use chrono::TimeZone;
+use slint::{ComponentHandle, Model, VecModel};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+
+/// 打开会话时增量同步最多向前翻的页数，超过后丢弃更早的缓存重新开始
+const MAX_SYNC_PAGES: usize = 10;
+/// 从搜索结果定位的消息高亮显示的时长
+const FOCUS_HIGHLIGHT_DURATION: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// 会话标识：单聊为对方ID，群聊为群ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConversationKey {
+    pub id: i64,
+    pub is_group: bool,
+}
+
+impl ConversationKey {
+    pub fn person(id: i64) -> Self {
+        Self {
+            id,
+            is_group: false,
+        }
+    }
+
+    pub fn group(id: i64) -> Self {
+        Self { id, is_group: true }
+    }
+
+    /// 根据事件中的 `target_type` 确定会话类型
+    pub fn from_target(id: i64, target_type: &str) -> Self {
+        Self {
+            id,
+            is_group: target_type == "group",
+        }
+    }
+
+    /// 聊天列表中某一项对应的会话
+    pub fn of_chat(chat: &ChatItem) -> Self {
+        Self {
+            id: chat.id as i64,
+            is_group: chat.is_group,
+        }
+    }
+
+    /// 根据消息的收发双方确定所属会话：群消息归到群，单聊归到对方
+    pub fn of_message(message: &ChatMessage, user_id: i64) -> Self {
+        if message.target_type == "group" {
+            Self::group(message.receiver_id)
+        } else if message.sender_id == user_id {
+            Self::person(message.receiver_id)
+        } else {
+            Self::person(message.sender_id)
+        }
+    }
+
+    pub fn target_type(&self) -> &'static str {
+        if self.is_group {
+            "group"
+        } else {
+            "person"
+        }
+    }
+}
+
+/// 按会话保存收发的消息。只有当前打开的会话会同步到 `Store.message-items`，
+/// 其他会话只更新聊天列表中的预览、时间和未读数
+pub struct Conversations {
+    weak_main: slint::Weak<Main>,
+    user_id: i64,
+    read_tracker: Arc<ReadTracker>,
+    network: Arc<NetworkClient>,
+    rt: Arc<Runtime>,
+    cache: Arc<MessageCache>,
+    messages: RefCell<HashMap<ConversationKey, Vec<ChatMessage>>>,
+    /// 当前会话的消息列表，始终是 `Store.message-items` 绑定的同一个模型，
+    /// 收发消息只在原地增改行，不重建模型
+    visible: Rc<VecModel<MessageItem>>,
+    /// 当前会话的增量同步和翻页请求，切换会话时取消
+    sync_request: RequestSlot,
+    history_request: RequestSlot,
+    /// 当前会话中图片消息的缩略图
+    images: Rc<Images>,
+    /// 文件消息下载到本机的位置
+    downloads: Downloads,
+    avatars: Rc<Avatars>,
+}
+
+impl Conversations {
+    pub fn new(
+        weak_main: slint::Weak<Main>,
+        user_id: i64,
+        read_tracker: Arc<ReadTracker>,
+        network: Arc<NetworkClient>,
+        rt: Arc<Runtime>,
+        cache: Arc<MessageCache>,
+        avatars: Rc<Avatars>,
+    ) -> Self {
+        let visible = Rc::new(VecModel::default());
+        if let Some(window) = weak_main.upgrade() {
+            window
+                .global::<Store>()
+                .set_message_items(slint::ModelRc::from(visible.clone()));
+        }
+        let images = Rc::new(Images::new(
+            weak_main.clone(),
+            user_id,
+            network.clone(),
+            rt.clone(),
+            visible.clone(),
+        ));
+        Self {
+            weak_main,
+            user_id,
+            read_tracker,
+            network,
+            rt,
+            cache,
+            messages: RefCell::new(HashMap::new()),
+            visible,
+            sync_request: RequestSlot::default(),
+            history_request: RequestSlot::default(),
+            images,
+            downloads: Downloads::load(user_id),
+            avatars,
+        }
+    }
+
+    pub fn images(&self) -> &Rc<Images> {
+        &self.images
+    }
+
+    pub fn downloads(&self) -> &Downloads {
+        &self.downloads
+    }
+
+    /// 当前打开的会话
+    pub fn current(&self, window: &Main) -> ConversationKey {
+        let store = window.global::<Store>();
+        ConversationKey {
+            id: store.get_current_chat() as i64,
+            is_group: store.get_current_chat_is_group(),
+        }
+    }
+
+    /// 打开会话：先显示本地缓存的最新一页消息，再在后台与服务端增量同步。
+    /// `pending` 为发件箱中尚未发出的消息
+    pub fn open(self: &Rc<Self>, key: ConversationKey, pending: Vec<OutboxEntry>) {
+        self.show(key, None, pending);
+    }
+
+    /// 从搜索结果打开会话并定位到 `message_id`：显示这条消息之前的一页和之后的全部缓存消息
+    pub fn open_at(
+        self: &Rc<Self>,
+        key: ConversationKey,
+        message_id: i64,
+        pending: Vec<OutboxEntry>,
+    ) {
+        self.show(key, Some(message_id), pending);
+    }
+
+    fn show(self: &Rc<Self>, key: ConversationKey, anchor: Option<i64>, pending: Vec<OutboxEntry>) {
+        let Some(window) = self.weak_main.upgrade() else {
+            return;
+        };
+        self.sync_request.cancel();
+        self.history_request.cancel();
+        let cached = match anchor {
+            Some(message_id) => self
+                .cache
+                .messages(key, Some(message_id), HISTORY_PAGE_SIZE)
+                .and_then(|mut older| {
+                    older.extend(self.cache.messages_from(key, message_id)?);
+                    Ok(older)
+                }),
+            None => self.cache.messages(key, None, HISTORY_PAGE_SIZE),
+        }
+        .unwrap_or_else(|e| {
+            println!("[错误] 读取本地缓存失败: {}", e);
+            Vec::new()
+        });
+        println!("[调试] 从本地缓存读取消息，数量: {}", cached.len());
+        let messages = {
+            let mut conversations = self.messages.borrow_mut();
+            let messages = conversations.entry(key).or_default();
+            // 尚未得到服务端确认的发出消息只在内存中。
+            // 没有服务端ID的收到消息不保留，由随后的增量同步带着ID补上
+            let unconfirmed: Vec<ChatMessage> = messages
+                .drain(..)
+                .filter(|message| self.is_unconfirmed(message))
+                .collect();
+            *messages = cached
+                .into_iter()
+                .map(ChatMessage::from)
+                .chain(unconfirmed)
+                .collect();
+            messages.clone()
+        };
+        let store = window.global::<Store>();
+        // 是否还有更早的消息要翻页时才能知道
+        store.set_history_has_more(true);
+        store.set_history_loading(false);
+
+        let mut pending: Vec<OutboxEntry> = pending
+            .into_iter()
+            .filter(|entry| ConversationKey::of_message(&entry.message, self.user_id) == key)
+            .collect();
+        let mut message_items = Vec::with_capacity(messages.len() + pending.len());
+        for message in &messages {
+            // 本地记录的发出消息如果还在发件箱中，以发件箱的状态为准
+            let entry = message.client_id.as_ref().and_then(|client_id| {
+                let index = pending.iter().position(|entry| &entry.id == client_id)?;
+                Some(pending.remove(index))
+            });
+            match entry {
+                Some(entry) => {
+                    let mut item = self.message_item(message, entry.status.as_str());
+                    item.id = entry.id.into();
+                    message_items.push(item);
+                }
+                None => message_items.push(self.message_item(message, "")),
+            }
+        }
+        // 追加发件箱中尚未发出的消息
+        for entry in pending {
+            let mut item = self.message_item(&entry.message, entry.status.as_str());
+            item.id = entry.id.into();
+            message_items.push(item);
+        }
+        println!("[调试] 消息项已创建，数量: {}", message_items.len());
+
+        self.visible.set_vec(message_items);
+        self.images.load_visible();
+        store.set_current_chat(key.id as i32);
+        store.set_current_chat_is_group(key.is_group);
+        if let Some(chat) = store
+            .get_chat_items()
+            .iter()
+            .find(|chat| ConversationKey::of_chat(chat) == key)
+        {
+            store.set_current_chat_name(chat.name);
+        }
+
+        // 打开会话即视为已读
+        read_state::clear_unread(&window, key);
+        if let Some(last_received_id) = messages
+            .iter()
+            .filter(|message| message.sender_id != self.user_id)
+            .filter_map(|message| message.message_id)
+            .max()
+        {
+            self.read_tracker
+                .mark_read(key.id, key.target_type(), last_received_id);
+        }
+        match anchor {
+            Some(message_id) => focus_message(&window, message_id),
+            None => {
+                store.set_focus_message_id(Default::default());
+                window.invoke_scroll_to_bottom();
+            }
+        }
+
+        self.sync(key);
+    }
+
+    /// 拉取上次同步之后的新消息写入缓存；服务器不可达时保持显示缓存内容
+    fn sync(self: &Rc<Self>, key: ConversationKey) {
+        let since = self.cache.sync_cursor(key).unwrap_or_else(|e| {
+            println!("[错误] 读取同步位置失败: {}", e);
+            None
+        });
+        let network = self.network.clone();
+        let user_id = self.user_id;
+        let conversations = self.clone();
+        self.sync_request.replace(request::spawn_local(
+            &self.rt,
+            async move {
+                network
+                    .get_messages_since(key.id, key.is_group, user_id, since, MAX_SYNC_PAGES)
+                    .await
+            },
+            move |result| match result {
+                Ok(page) => conversations.apply_sync(key, since, page),
+                Err(ApiError::Unauthorized) => {
+                    println!("[错误] 同步会话{}失败，登录已失效", key.id);
+                    if let Some(window) = conversations.weak_main.upgrade() {
+                        window
+                            .global::<Store>()
+                            .set_connection_status(ConnectionStatus::AuthFailed);
+                    }
+                }
+                Err(e) => println!("[调试] 同步会话{}失败，继续使用本地缓存: {}", key.id, e),
+            },
+        ));
+    }
+
+    fn apply_sync(&self, key: ConversationKey, since: Option<i64>, page: HistoryPage) {
+        println!(
+            "[调试] 会话{}同步完成，新消息数量: {}",
+            key.id,
+            page.messages.len()
+        );
+        // 第一次同步或者翻页后仍未接上，缓存中更早的消息可能不连续
+        let gap = since.is_none() || page.has_more;
+        let result = self
+            .cache
+            .save_messages(key, &page.messages, gap)
+            .and_then(
+                |_| match page.messages.iter().map(|message| message.id).max() {
+                    Some(last_id) => self.cache.set_sync_cursor(key, last_id),
+                    None => Ok(()),
+                },
+            );
+        if let Err(e) = result {
+            println!("[错误] 写入本地缓存失败: {}", e);
+        }
+        if let Some(last) = page.messages.last() {
+            let _ = self
+                .cache
+                .touch_conversation(key, &ChatMessage::from(last.clone()));
+        }
+
+        let Some(window) = self.weak_main.upgrade() else {
+            return;
+        };
+        if key != self.current(&window) || page.messages.is_empty() {
+            return;
+        }
+        if gap {
+            self.reload(&window, key);
+            return;
+        }
+        for message in page.messages.into_iter().map(ChatMessage::from) {
+            let exists = {
+                let mut conversations = self.messages.borrow_mut();
+                let messages = conversations.entry(key).or_default();
+                let exists = messages
+                    .iter()
+                    .any(|existing| existing.message_id == message.message_id);
+                if !exists {
+                    messages.push(message.clone());
+                }
+                exists
+            };
+            if exists {
+                continue;
+            }
+            if message.sender_id != self.user_id {
+                if let Some(message_id) = message.message_id {
+                    self.read_tracker
+                        .mark_read(key.id, key.target_type(), message_id);
+                }
+            }
+            self.append_visible(&window, self.message_item(&message, ""), false);
+        }
+    }
+
+    /// 缓存与界面不再连续时，从缓存重新显示最新一页，保留尚未确认的发出消息
+    fn reload(&self, window: &Main, key: ConversationKey) {
+        let cached = self
+            .cache
+            .messages(key, None, HISTORY_PAGE_SIZE)
+            .unwrap_or_default();
+        // 与 `is_unconfirmed` 一致：自己发出、没有服务端ID的消息，它们的气泡ID就是客户端消息ID
+        let unconfirmed: Vec<MessageItem> = self
+            .visible
+            .iter()
+            .filter(|item| item.send_type == "send" && item.server_id.is_empty())
+            .collect();
+        let messages: Vec<ChatMessage> = cached.into_iter().map(ChatMessage::from).collect();
+        let mut items: Vec<MessageItem> = messages
+            .iter()
+            .map(|message| self.message_item(message, ""))
+            .collect();
+        items.extend(unconfirmed);
+        {
+            let mut conversations = self.messages.borrow_mut();
+            let current = conversations.entry(key).or_default();
+            current.retain(|message| self.is_unconfirmed(message));
+            current.splice(0..0, messages);
+        }
+        self.visible.set_vec(items);
+        self.images.load_visible();
+        window.global::<Store>().set_history_has_more(true);
+        window.invoke_scroll_to_bottom();
+    }
+
+    /// 自己发出、还没有得到服务端确认的消息
+    fn is_unconfirmed(&self, message: &ChatMessage) -> bool {
+        message.sender_id == self.user_id
+            && message.client_id.is_some()
+            && message.message_id.is_none()
+    }
+
+    /// 发出的消息得到服务端确认后记下服务端ID，并写入缓存
+    pub fn confirm_sent(&self, client_id: &str, server_id: i64) {
+        let mut conversations = self.messages.borrow_mut();
+        for (key, messages) in conversations.iter_mut() {
+            if let Some(message) = messages
+                .iter_mut()
+                .find(|message| message.client_id.as_deref() == Some(client_id))
+            {
+                message.message_id = Some(server_id);
+                if let Err(e) = self.cache.save_chat_message(*key, message) {
+                    println!("[错误] 写入本地缓存失败: {}", e);
+                }
+                return;
+            }
+        }
+    }
+
+    /// 按消息气泡的ID（自己发出的为客户端消息ID，其他为服务端ID）查找消息
+    pub fn find_message(&self, id: &str) -> Option<ChatMessage> {
+        self.messages
+            .borrow()
+            .values()
+            .flatten()
+            .find(|message| {
+                message.client_id.as_deref() == Some(id)
+                    || message
+                        .message_id
+                        .is_some_and(|server_id| server_id.to_string() == id)
+            })
+            .cloned()
+    }
+
+    /// 修改一条尚未得到服务端确认的发出消息，例如文件上传完成后记下文件路径
+    pub fn update_message(&self, client_id: &str, update: impl FnOnce(&mut ChatMessage)) {
+        if let Some(message) = self
+            .messages
+            .borrow_mut()
+            .values_mut()
+            .flatten()
+            .find(|message| message.client_id.as_deref() == Some(client_id))
+        {
+            update(message);
+        }
+    }
+
+    /// 用缓存中的会话摘要恢复聊天列表的预览和顺序
+    pub fn restore_previews(&self) {
+        let Some(window) = self.weak_main.upgrade() else {
+            return;
+        };
+        let summaries = self.cache.conversations().unwrap_or_else(|e| {
+            println!("[错误] 读取会话摘要失败: {}", e);
+            Vec::new()
+        });
+        let chat_items = window.global::<Store>().get_chat_items();
+        let Some(chat_items) = chat_items.as_any().downcast_ref::<VecModel<ChatItem>>() else {
+            return;
+        };
+        let mut items: Vec<(usize, ChatItem)> = chat_items
+            .iter()
+            .map(|mut chat| {
+                let key = ConversationKey::of_chat(&chat);
+                let position = summaries.iter().position(|summary| summary.key == key);
+                if let Some(summary) = position.map(|index| &summaries[index]) {
+                    chat.text = summary.preview.clone().into();
+                    chat.text_type = summary.message_type.clone().into();
+                    chat.time = format_chat_time(summary.last_timestamp).into();
+                }
+                (position.unwrap_or(usize::MAX), chat)
+            })
+            .collect();
+        // 有消息的会话按最后一条消息的时间排在前面，其余保持原来的顺序
+        items.sort_by_key(|(position, _)| *position);
+        chat_items.set_vec(items.into_iter().map(|(_, chat)| chat).collect::<Vec<_>>());
+    }
+
+    /// 收到一条消息，放入所属会话
+    pub fn receive(&self, mut message: ChatMessage) {
+        let Some(window) = self.weak_main.upgrade() else {
+            return;
+        };
+        // 没有任何ID的消息补一个本地ID，界面点击、重发等都按ID找消息
+        if message.message_id.is_none() && message.client_id.is_none() {
+            message.client_id = Some(new_message_id());
+        }
+        let key = ConversationKey::of_message(&message, self.user_id);
+        {
+            let mut conversations = self.messages.borrow_mut();
+            let messages = conversations.entry(key).or_default();
+            // 断线重连补拉的消息可能已经收到过
+            if message.message_id.is_some()
+                && messages
+                    .iter()
+                    .any(|existing| existing.message_id == message.message_id)
+            {
+                return;
+            }
+            messages.push(message.clone());
+        }
+        if let Err(e) = self
+            .cache
+            .save_chat_message(key, &message)
+            .and_then(|_| self.cache.touch_conversation(key, &message))
+        {
+            println!("[错误] 写入本地缓存失败: {}", e);
+        }
+
+        let incoming = message.sender_id != self.user_id;
+        update_preview(&window, key, &message);
+        if key == self.current(&window) {
+            self.append_visible(&window, self.message_item(&message, ""), false);
+            // 正在查看的会话直接前进已读位置
+            if let (true, Some(message_id)) = (incoming, message.message_id) {
+                self.read_tracker
+                    .mark_read(key.id, key.target_type(), message_id);
+            }
+        } else if incoming {
+            read_state::increment_unread(&window, key);
+        }
+    }
+
+    /// 自己发出一条消息，先以发送中的状态显示
+    pub fn send(&self, id: &str, message: ChatMessage) {
+        let Some(window) = self.weak_main.upgrade() else {
+            return;
+        };
+        let key = ConversationKey::of_message(&message, self.user_id);
+        let mut item = self.message_item(&message, MessageStatus::Sending.as_str());
+        item.id = id.into();
+        update_preview(&window, key, &message);
+        if let Err(e) = self.cache.touch_conversation(key, &message) {
+            println!("[错误] 写入本地缓存失败: {}", e);
+        }
+        if key == self.current(&window) {
+            // 自己发出的消息总是滚动到底部
+            self.append_visible(&window, item, true);
+        }
+        self.messages
+            .borrow_mut()
+            .entry(key)
+            .or_default()
+            .push(message);
+    }
+
+    /// 加载当前会话更早的一页消息，插入到列表顶部
+    pub fn load_older(self: &Rc<Self>) {
+        let Some(window) = self.weak_main.upgrade() else {
+            return;
+        };
+        let store = window.global::<Store>();
+        if store.get_history_loading() || !store.get_history_has_more() {
+            return;
+        }
+        let key = self.current(&window);
+        let before_id = self
+            .messages
+            .borrow()
+            .get(&key)
+            .and_then(|messages| messages.iter().find_map(|message| message.message_id));
+        println!(
+            "[调试] 加载更早的消息，会话: {}，起点: {:?}",
+            key.id, before_id
+        );
+
+        // 优先从本地缓存翻页，缓存翻到头后再向服务端请求
+        match self.cache.messages(key, before_id, HISTORY_PAGE_SIZE) {
+            Ok(messages) if !messages.is_empty() => {
+                self.prepend(
+                    key,
+                    HistoryPage {
+                        messages,
+                        has_more: true,
+                    },
+                );
+                return;
+            }
+            Ok(_) => {}
+            Err(e) => println!("[错误] 读取本地缓存失败: {}", e),
+        }
+        store.set_history_loading(true);
+
+        let network = self.network.clone();
+        let user_id = self.user_id;
+        let conversations = self.clone();
+        self.history_request.replace(request::spawn_local(
+            &self.rt,
+            async move {
+                network
+                    .get_chat_history(key.id, key.is_group, user_id, before_id, HISTORY_PAGE_SIZE)
+                    .await
+            },
+            move |result| match result {
+                Ok(page) => {
+                    if let Err(e) = conversations
+                        .cache
+                        .save_messages(key, &page.messages, false)
+                    {
+                        println!("[错误] 写入本地缓存失败: {}", e);
+                    }
+                    conversations.prepend(key, page);
+                }
+                Err(e) => {
+                    println!("[错误] 加载更早的消息失败: {}", e);
+                    conversations.finish_loading();
+                }
+            },
+        ));
+    }
+
+    fn finish_loading(&self) {
+        if let Some(window) = self.weak_main.upgrade() {
+            window.global::<Store>().set_history_loading(false);
+        }
+    }
+
+    /// 把更早的一页消息插入到会话开头，并保持当前看到的消息不动
+    fn prepend(&self, key: ConversationKey, page: HistoryPage) {
+        let Some(window) = self.weak_main.upgrade() else {
+            return;
+        };
+        // 加载期间已经切换到其他会话，丢弃结果
+        if key != self.current(&window) {
+            return;
+        }
+        let older: Vec<ChatMessage> = {
+            let mut conversations = self.messages.borrow_mut();
+            let messages = conversations.entry(key).or_default();
+            let older: Vec<ChatMessage> = page
+                .messages
+                .into_iter()
+                .map(ChatMessage::from)
+                .filter(|message| {
+                    !messages
+                        .iter()
+                        .any(|existing| existing.message_id == message.message_id)
+                })
+                .collect();
+            messages.splice(0..0, older.iter().cloned());
+            older
+        };
+        for (index, message) in older.iter().enumerate() {
+            self.visible.insert(index, self.message_item(message, ""));
+        }
+        self.images.load_visible();
+
+        let store = window.global::<Store>();
+        store.set_history_has_more(page.has_more);
+        store.set_history_loading(false);
+        window.invoke_restore_scroll_anchor();
+    }
+
+    /// 在当前会话末尾追加一条消息；正在查看更早的消息时不打断滚动位置
+    fn append_visible(&self, window: &Main, item: MessageItem, follow: bool) {
+        let at_bottom = window.invoke_is_at_bottom();
+        self.visible.push(item);
+        self.images.load_visible();
+        if follow || at_bottom {
+            window.invoke_scroll_to_bottom();
+        }
+    }
+
+    /// 更新当前会话中某条消息，返回是否找到
+    pub fn update_visible(&self, id: &str, update: impl FnOnce(&mut MessageItem)) -> bool {
+        let Some(index) = self.visible.iter().position(|item| item.id.as_str() == id) else {
+            return false;
+        };
+        let mut item = self.visible.row_data(index).unwrap();
+        update(&mut item);
+        self.visible.set_row_data(index, item);
+        true
+    }
+
+    fn message_item(&self, message: &ChatMessage, status: &str) -> MessageItem {
+        let server_id = message
+            .message_id
+            .map(|id| id.to_string())
+            .unwrap_or_default();
+        // 列表行的ID必须唯一：优先用客户端消息ID，其次用服务端ID
+        let id = match &message.client_id {
+            Some(client_id) => client_id.clone(),
+            None if !server_id.is_empty() => server_id.clone(),
+            None => new_message_id(),
+        };
+        let incoming = message.sender_id != self.user_id;
+        let is_file = message.message_type == "file";
+        let is_image = message.message_type == "image";
+        let avatar = self.avatars.get(message.sender_id, &message.username);
+        let (file_state, progress) = if is_file {
+            self.downloads.state(message)
+        } else {
+            (transfer::FileState::Remote, 0.0)
+        };
+        MessageItem {
+            id: id.into(),
+            server_id: server_id.into(),
+            text: message.content.clone().into(),
+            sender_id: message.sender_id as i32,
+            avatar: avatar.image,
+            avatar_initial: avatar.initial,
+            text_type: if is_file {
+                "file"
+            } else if is_image {
+                "image"
+            } else {
+                "text"
+            }
+            .into(),
+            send_type: if incoming {
+                "receive".into()
+            } else {
+                "send".into()
+            },
+            // 群聊中收到的消息显示发送者
+            sender_name: if incoming && message.target_type == "group" {
+                message.username.clone().into()
+            } else {
+                Default::default()
+            },
+            time: message.timestamp.to_string().into(),
+            status: status.into(),
+            file_name: message.file_name.clone().unwrap_or_default().into(),
+            file_size: message
+                .file_size
+                .map(transfer::format_size)
+                .unwrap_or_default()
+                .into(),
+            file_state: file_state.as_str().into(),
+            progress,
+            file_path: message.file_path.clone().unwrap_or_default().into(),
+            image: message
+                .file_path
+                .as_deref()
+                .filter(|_| is_image)
+                .and_then(|file_path| self.images.cached(file_path))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// 滚动到搜索命中的消息并短暂高亮
+fn focus_message(window: &Main, message_id: i64) {
+    let focus_id: slint::SharedString = message_id.to_string().into();
+    let store = window.global::<Store>();
+    store.set_focus_message_id(focus_id.clone());
+    let weak_main = window.as_weak();
+    slint::Timer::single_shot(FOCUS_HIGHLIGHT_DURATION, move || {
+        let Some(window) = weak_main.upgrade() else {
+            return;
+        };
+        let store = window.global::<Store>();
+        if store.get_focus_message_id() == focus_id {
+            store.set_focus_message_id(Default::default());
+        }
+    });
+}
+
+/// 更新聊天列表中会话的最后一条消息和时间，并把会话移到最前面
+fn update_preview(window: &Main, key: ConversationKey, message: &ChatMessage) {
+    let chat_items = window.global::<Store>().get_chat_items();
+    let Some(chat_items) = chat_items.as_any().downcast_ref::<VecModel<ChatItem>>() else {
+        return;
+    };
+    let Some(index) = chat_items
+        .iter()
+        .position(|chat| ConversationKey::of_chat(&chat) == key)
+    else {
+        return;
+    };
+    let mut chat = chat_items.remove(index);
+    chat.text = message.content.clone().into();
+    chat.text_type = message.message_type.clone().into();
+    chat.time = format_chat_time(message.timestamp).into();
+    chat_items.insert(0, chat);
+}
+
+/// 聊天列表中的时间：当天显示时分，更早显示日期
+pub fn format_chat_time(timestamp: i64) -> String {
+    let Some(time) = chrono::Local.timestamp_opt(timestamp, 0).single() else {
+        return String::new();
+    };
+    if time.date_naive() == chrono::Local::now().date_naive() {
+        time.format("%H:%M").to_string()
+    } else {
+        time.format("%m-%d").to_string()
+    }
+}
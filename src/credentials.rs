@@ -0,0 +1,141 @@
+use crate::config;
+use anyhow::{anyhow, Result};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// 随机数在密文前面保存
+const NONCE_LEN: usize = 12;
+
+/// 勾选“记住我”后保存的登录凭据，下次启动时自动登录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedCredentials {
+    pub user_id: i64,
+    pub username: String,
+    pub token: String,
+    /// 旧版本保存的凭据没有 refresh token
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+fn credentials_path() -> PathBuf {
+    config::data_dir().join("credentials.dat")
+}
+
+/// 系统钥匙串（macOS 钥匙串、Windows 凭据管理器、Linux Secret Service）中保存加密密钥的条目。
+/// 密钥不落在数据目录里，拷走数据目录无法解密凭据和 token；
+/// 能以当前用户身份读取钥匙串的程序仍然可以解密
+fn key_entry() -> Result<keyring::Entry> {
+    Ok(keyring::Entry::new("me_chat", "credentials-key")?)
+}
+
+/// 只允许当前用户读写
+fn write_private(path: &Path, content: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, content)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+fn load_key() -> Result<Key> {
+    let key = key_entry()?.get_secret()?;
+    if key.len() != 32 {
+        return Err(anyhow!("钥匙串中的凭据密钥已损坏"));
+    }
+    Ok(*Key::from_slice(&key))
+}
+
+/// 钥匙串中还没有密钥时生成一个；钥匙串不可用时返回错误，不退回到明文保存
+fn load_or_create_key() -> Result<Key> {
+    match key_entry()?.get_secret() {
+        Ok(key) if key.len() == 32 => return Ok(*Key::from_slice(&key)),
+        Ok(_) => println!("[错误] 钥匙串中的凭据密钥已损坏，重新生成"),
+        Err(keyring::Error::NoEntry) => {}
+        Err(e) => return Err(e.into()),
+    }
+    let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+    key_entry()?.set_secret(key.as_slice())?;
+    // 之前的版本把密钥保存在数据目录的文件中
+    let _ = std::fs::remove_file(config::data_dir().join("credentials.key"));
+    Ok(key)
+}
+
+/// 加密一段数据，返回随机数和密文
+pub fn seal(plaintext: &[u8]) -> Result<Vec<u8>> {
+    let cipher = ChaCha20Poly1305::new(&load_or_create_key()?);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| anyhow!("加密失败"))?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    Ok(sealed)
+}
+
+/// 解密 `seal` 的结果，密钥变化或内容被篡改时失败
+pub fn unseal(sealed: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        return Err(anyhow!("密文长度不正确"));
+    }
+    let cipher = ChaCha20Poly1305::new(&load_key()?);
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow!("解密失败"))
+}
+
+pub fn save(credentials: &SavedCredentials) -> Result<()> {
+    let sealed = seal(&serde_json::to_vec(credentials)?)?;
+    write_private(&credentials_path(), &sealed)?;
+    println!("[调试] 已保存登录凭据: {}", credentials.username);
+    Ok(())
+}
+
+/// 读取保存的凭据；文件无法解密时删除它，回到手动登录
+pub fn load() -> Option<SavedCredentials> {
+    let sealed = std::fs::read(credentials_path()).ok()?;
+    let credentials = unseal(&sealed)
+        .and_then(|plaintext| Ok(serde_json::from_slice::<SavedCredentials>(&plaintext)?));
+    match credentials {
+        Ok(credentials) => Some(credentials),
+        Err(e) => {
+            println!("[错误] 读取登录凭据失败: {}", e);
+            clear();
+            None
+        }
+    }
+}
+
+/// token 刷新后更新保存的凭据；没有保存该账号的凭据时不做任何事
+pub fn update_tokens(user_id: i64, token: &str, refresh_token: Option<&str>) {
+    let Some(mut saved) = load() else {
+        return;
+    };
+    if saved.user_id != user_id {
+        return;
+    }
+    saved.token = token.to_string();
+    if let Some(refresh_token) = refresh_token {
+        saved.refresh_token = Some(refresh_token.to_string());
+    }
+    if let Err(e) = save(&saved) {
+        println!("[错误] 更新登录凭据失败: {}", e);
+    }
+}
+
+/// 删除保存的凭据，下次启动需要手动登录
+pub fn clear() {
+    let path = credentials_path();
+    if path.exists() {
+        if let Err(e) = std::fs::remove_file(&path) {
+            println!("[错误] 删除登录凭据失败: {}", e);
+        }
+    }
+}
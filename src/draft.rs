@@ -0,0 +1,48 @@
+use crate::config;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// 输入框中尚未发送的内容。关闭主窗口时保存，下次登录同一账号时恢复
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Draft {
+    pub chat_id: i64,
+    #[serde(default)]
+    pub is_group: bool,
+    pub text: String,
+}
+
+fn draft_path(user_id: i64) -> PathBuf {
+    config::account_data_dir(user_id).join("draft.json")
+}
+
+/// 保存草稿；内容为空时删除之前保存的草稿
+pub fn save(user_id: i64, draft: &Draft) {
+    let path = draft_path(user_id);
+    if draft.text.is_empty() {
+        let _ = std::fs::remove_file(path);
+        return;
+    }
+    let result = serde_json::to_string(draft)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, content)?;
+            Ok(())
+        });
+    match result {
+        Ok(()) => println!("[调试] 已保存会话{}的草稿", draft.chat_id),
+        Err(e) => println!("[错误] 保存草稿失败: {}", e),
+    }
+}
+
+/// 取出保存的草稿，取出后删除文件
+pub fn take(user_id: i64) -> Option<Draft> {
+    let path = draft_path(user_id);
+    let content = std::fs::read_to_string(&path).ok()?;
+    let _ = std::fs::remove_file(&path);
+    serde_json::from_str(&content)
+        .inspect_err(|e| println!("[错误] 解析草稿文件失败: {}", e))
+        .ok()
+}
@@ -0,0 +1,330 @@
+use crate::api::request::{self, RequestSlot};
+use crate::api::{ApiError, GroupInfo, NetworkClient};
+use crate::{AppGlobal, GroupCandidate, GroupMemberItem, GroupPanel, Main, Store};
+use slint::{ComponentHandle, Model, ModelRc, VecModel};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+
+/// 群名称的最大长度
+const MAX_GROUP_NAME_LEN: usize = 20;
+
+/// 我加入的群聊，以及建群、群信息面板上的操作
+pub struct Groups {
+    weak_main: slint::Weak<Main>,
+    user_id: i64,
+    network: Arc<NetworkClient>,
+    rt: Arc<Runtime>,
+    groups: RefCell<Vec<GroupInfo>>,
+    /// 建群、邀请、退群同一时间只进行一个
+    request: RequestSlot,
+    /// 刷新群成员，关闭面板后不再需要
+    detail_request: RequestSlot,
+}
+
+impl Groups {
+    pub fn new(
+        weak_main: slint::Weak<Main>,
+        user_id: i64,
+        network: Arc<NetworkClient>,
+        rt: Arc<Runtime>,
+    ) -> Self {
+        Self {
+            weak_main,
+            user_id,
+            network,
+            rt,
+            groups: RefCell::new(Vec::new()),
+            request: RequestSlot::default(),
+            detail_request: RequestSlot::default(),
+        }
+    }
+
+    pub fn list(&self) -> Vec<GroupInfo> {
+        self.groups.borrow().clone()
+    }
+
+    pub fn set_groups(&self, groups: Vec<GroupInfo>) {
+        *self.groups.borrow_mut() = groups;
+    }
+
+    /// 打开建群面板，可以从好友中选择成员
+    pub fn open_create(&self) {
+        let Some(window) = self.weak_main.upgrade() else {
+            return;
+        };
+        self.set_candidates(&window, &[]);
+        let store = window.global::<Store>();
+        store.set_group_error("".into());
+        store.set_group_panel(GroupPanel::Create);
+    }
+
+    /// 打开当前群聊的信息面板，显示成员并可以邀请其他好友，同时在后台刷新成员列表
+    pub fn open_info(self: &Rc<Self>) {
+        let Some(window) = self.weak_main.upgrade() else {
+            return;
+        };
+        let store = window.global::<Store>();
+        if !store.get_current_chat_is_group() {
+            return;
+        }
+        let group_id = store.get_current_chat() as i64;
+        if let Some(group) = self.find(group_id) {
+            self.show_members(&window, &group);
+        }
+        store.set_group_error("".into());
+        store.set_group_panel(GroupPanel::Info);
+
+        let network = self.network.clone();
+        let groups = Rc::downgrade(self);
+        self.detail_request.replace(request::spawn_local(
+            &self.rt,
+            async move { network.get_group(group_id).await },
+            move |result| {
+                let Some(groups) = groups.upgrade() else {
+                    return;
+                };
+                let group = match result {
+                    Ok(group) => group,
+                    Err(e) => {
+                        println!("[错误] 获取群{}的成员失败: {}", group_id, e);
+                        return;
+                    }
+                };
+                groups.upsert(group.clone());
+                let Some(window) = groups.weak_main.upgrade() else {
+                    return;
+                };
+                let store = window.global::<Store>();
+                // 等待期间可能已经关闭面板或切换了会话
+                if store.get_group_panel() == GroupPanel::Info
+                    && store.get_current_chat() as i64 == group_id
+                    && store.get_current_chat_is_group()
+                {
+                    groups.show_members(&window, &group);
+                }
+            },
+        ));
+    }
+
+    /// 勾选或取消勾选一个好友
+    pub fn toggle(&self, index: usize) {
+        let Some(window) = self.weak_main.upgrade() else {
+            return;
+        };
+        let candidates = window.global::<Store>().get_group_candidates();
+        if let Some(mut candidate) = candidates.row_data(index) {
+            candidate.checked = !candidate.checked;
+            candidates.set_row_data(index, candidate);
+        }
+    }
+
+    /// 用勾选的好友创建群聊，成功后打开新群
+    pub fn create(self: &Rc<Self>, name: &str, on_changed: impl FnOnce() + 'static) {
+        let Some(window) = self.weak_main.upgrade() else {
+            return;
+        };
+        let store = window.global::<Store>();
+        let name = name.trim().to_string();
+        let member_ids = checked_candidates(&window);
+        let error = if name.is_empty() {
+            "请输入群名称"
+        } else if name.chars().count() > MAX_GROUP_NAME_LEN {
+            "群名称不能超过20个字"
+        } else if member_ids.is_empty() {
+            "请至少选择一位好友"
+        } else {
+            ""
+        };
+        if !error.is_empty() {
+            store.set_group_error(error.into());
+            return;
+        }
+        println!("[调试] 创建群聊: {}，成员: {:?}", name, member_ids);
+        let network = self.network.clone();
+        self.run(
+            async move { network.create_group(name, member_ids).await },
+            move |groups, group| {
+                let group_id = group.id;
+                groups.upsert(group);
+                on_changed();
+                if let Some(window) = groups.weak_main.upgrade() {
+                    window
+                        .global::<AppGlobal>()
+                        .invoke_chat_selected(group_id as i32, true);
+                }
+            },
+        );
+    }
+
+    /// 把勾选的好友邀请进当前群聊
+    pub fn invite(self: &Rc<Self>, on_changed: impl FnOnce() + 'static) {
+        let Some(window) = self.weak_main.upgrade() else {
+            return;
+        };
+        let store = window.global::<Store>();
+        let group_id = store.get_current_chat() as i64;
+        let user_ids = checked_candidates(&window);
+        if user_ids.is_empty() {
+            store.set_group_error("请选择要邀请的好友".into());
+            return;
+        }
+        println!("[调试] 邀请{:?}加入群{}", user_ids, group_id);
+        let network = self.network.clone();
+        self.run(
+            async move {
+                network.invite_to_group(group_id, user_ids).await?;
+                network.get_group(group_id).await
+            },
+            move |groups, group| {
+                groups.upsert(group);
+                on_changed();
+            },
+        );
+    }
+
+    /// 退出当前群聊，回到文件传输助手
+    pub fn leave(self: &Rc<Self>, on_changed: impl FnOnce() + 'static) {
+        let Some(window) = self.weak_main.upgrade() else {
+            return;
+        };
+        let group_id = window.global::<Store>().get_current_chat() as i64;
+        println!("[调试] 退出群{}", group_id);
+        let network = self.network.clone();
+        self.run(
+            async move { network.leave_group(group_id).await },
+            move |groups, ()| {
+                groups
+                    .groups
+                    .borrow_mut()
+                    .retain(|group| group.id != group_id);
+                if let Some(window) = groups.weak_main.upgrade() {
+                    let store = window.global::<Store>();
+                    if store.get_current_chat_is_group()
+                        && store.get_current_chat() as i64 == group_id
+                    {
+                        window
+                            .global::<AppGlobal>()
+                            .invoke_chat_selected(groups.user_id as i32, false);
+                    }
+                }
+                on_changed();
+            },
+        );
+    }
+
+    /// 执行群操作：期间显示忙碌状态，成功后关闭面板，失败时在面板上显示原因
+    fn run<T, Fut>(self: &Rc<Self>, request: Fut, on_success: impl FnOnce(&Rc<Self>, T) + 'static)
+    where
+        Fut: std::future::Future<Output = Result<T, ApiError>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let Some(window) = self.weak_main.upgrade() else {
+            return;
+        };
+        let store = window.global::<Store>();
+        store.set_group_busy(true);
+        store.set_group_error("".into());
+        let groups = Rc::downgrade(self);
+        self.request
+            .replace(request::spawn_local(&self.rt, request, move |result| {
+                let Some(groups) = groups.upgrade() else {
+                    return;
+                };
+                let Some(window) = groups.weak_main.upgrade() else {
+                    return;
+                };
+                let store = window.global::<Store>();
+                store.set_group_busy(false);
+                match result {
+                    Ok(value) => {
+                        store.set_group_panel(GroupPanel::None);
+                        on_success(&groups, value);
+                    }
+                    Err(e) => {
+                        println!("[错误] 群操作失败: {}", e);
+                        store.set_group_error(error_message(&e).into());
+                    }
+                }
+            }));
+    }
+
+    fn find(&self, group_id: i64) -> Option<GroupInfo> {
+        self.groups
+            .borrow()
+            .iter()
+            .find(|group| group.id == group_id)
+            .cloned()
+    }
+
+    fn upsert(&self, group: GroupInfo) {
+        let mut groups = self.groups.borrow_mut();
+        match groups.iter_mut().find(|old| old.id == group.id) {
+            Some(old) => *old = group,
+            None => groups.push(group),
+        }
+    }
+
+    fn show_members(&self, window: &Main, group: &GroupInfo) {
+        let members: Vec<GroupMemberItem> = group
+            .members
+            .iter()
+            .map(|member| GroupMemberItem {
+                id: member.user_id as i32,
+                name: member.username.clone().into(),
+            })
+            .collect();
+        window
+            .global::<Store>()
+            .set_group_members(ModelRc::new(VecModel::from(members)));
+        let member_ids: Vec<i64> = group.members.iter().map(|member| member.user_id).collect();
+        self.set_candidates(window, &member_ids);
+    }
+
+    /// 可选的好友：聊天列表中除自己和 `exclude` 以外的单聊
+    fn set_candidates(&self, window: &Main, exclude: &[i64]) {
+        let store = window.global::<Store>();
+        let candidates: Vec<GroupCandidate> = store
+            .get_chat_items()
+            .iter()
+            .filter(|chat| {
+                let id = chat.id as i64;
+                !chat.is_group && id != self.user_id && !exclude.contains(&id)
+            })
+            .map(|chat| GroupCandidate {
+                id: chat.id,
+                name: chat.name,
+                checked: false,
+            })
+            .collect();
+        store.set_group_candidates(ModelRc::new(VecModel::from(candidates)));
+    }
+}
+
+fn checked_candidates(window: &Main) -> Vec<i64> {
+    window
+        .global::<Store>()
+        .get_group_candidates()
+        .iter()
+        .filter(|candidate| candidate.checked)
+        .map(|candidate| candidate.id as i64)
+        .collect()
+}
+
+/// 群操作失败时提示给用户的信息
+fn error_message(error: &ApiError) -> String {
+    match error {
+        ApiError::Network(_) | ApiError::Timeout => "网络异常，请稍后重试".to_string(),
+        ApiError::Unauthorized => "登录已失效，请重新登录".to_string(),
+        ApiError::NotFound => "群聊不存在或已解散".to_string(),
+        ApiError::RateLimited { .. } => "操作过于频繁，请稍后再试".to_string(),
+        ApiError::Server { description, .. } if !description.is_empty() => {
+            format!("服务器错误：{}", description)
+        }
+        ApiError::Conflict
+        | ApiError::Server { .. }
+        | ApiError::Decode { .. }
+        | ApiError::File(_) => "服务器异常，请稍后再试".to_string(),
+    }
+}
@@ -1,25 +1,58 @@
 #![windows_subsystem = "windows"]
 
 use anyhow::Result;
+mod account;
 mod api;
+mod avatar;
+mod cache;
 mod config;
+mod conversation;
+mod credentials;
+mod draft;
+mod group;
+mod media;
+mod presence;
+mod read_state;
+mod search;
+mod settings;
+mod transfer;
+mod typing;
 mod websocket;
 mod window_handler;
-use api::NetworkClient;
+use api::request::RequestSlot;
+use api::{ApiError, AuthEvent, NetworkClient};
+use avatar::Avatars;
+use cache::MessageCache;
+use config::PresenceConfig;
+use conversation::{ConversationKey, Conversations};
 use dotenv::dotenv;
-use slint::{ComponentHandle, Image, Model, SharedPixelBuffer, VecModel};
+use group::Groups;
+use presence::IdleMonitor;
+use read_state::ReadTracker;
+use search::SearchPanel;
+use slint::{ComponentHandle, Image, Model, ModelRc};
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+use std::sync::atomic::AtomicU64;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::runtime::Runtime;
-use tokio::sync::Mutex;
-use websocket::{ChatMessage, WebSocketClient};
+use tokio::sync::broadcast;
+use tokio::task::AbortHandle;
+use transfer::Transfers;
+use typing::TypingNotifier;
+use websocket::{
+    new_message_id, ChatMessage, ConnectionEvent, ConnectionState, MessageStatus, Outbox,
+    ServerEvent, WebSocketClient,
+};
 use window_handler::{WindowEvents, WindowHandler};
 
 slint::slint! {
     import { Main } from "ui/main.slint";
     import { Login } from "ui/login.slint";
-    import { Store,AppGlobal,UserInfo,ChatItem } from "ui/store.slint";
+    import { Store,AppGlobal,UserInfo,ChatItem,ConnectionStatus,SearchResult,GroupPanel,GroupMemberItem,GroupCandidate } from "ui/store.slint";
     import { MessageList } from "ui/component/message-list.slint";
-    export { Main , Login , Store,AppGlobal,UserInfo,ChatItem }
+    export { Main , Login , Store,AppGlobal,UserInfo,ChatItem,ConnectionStatus,SearchResult,GroupPanel,GroupMemberItem,GroupCandidate }
 }
 
 impl WindowEvents for Main {
@@ -52,14 +85,1278 @@ impl WindowEvents for Login {
     }
 }
 
-type WsClient = Arc<Mutex<WebSocketClient>>;
+type WsClient = Arc<WebSocketClient>;
 
-fn create_ws_client(socket_url: String, token: String, rt: &Runtime) -> Result<WsClient> {
-    let mut ws_client = WebSocketClient::new(socket_url, token);
-    rt.block_on(async {
-        let _ = ws_client.connect().await;
+/// 登录得到的账号信息，在线登录和离线登录都会生成
+struct Session {
+    user_id: i64,
+    username: String,
+    token: String,
+}
+
+/// 主窗口打开期间运行的连接和后台任务，退出登录时统一停止
+struct MainSession {
+    client: Arc<NetworkClient>,
+    rt: Arc<Runtime>,
+    ws_client: WsClient,
+    tasks: Vec<AbortHandle>,
+    local_tasks: Vec<slint::JoinHandle<()>>,
+}
+
+impl MainSession {
+    fn close(self) {
+        for task in self.tasks {
+            task.abort();
+        }
+        for task in self.local_tasks {
+            task.abort();
+        }
+        self.client.clear_token();
+        // 发送 Close 帧需要等待网络，放到后台完成
+        let ws_client = self.ws_client;
+        self.rt.spawn(async move {
+            ws_client.disconnect().await;
+        });
+    }
+}
+
+fn create_ws_client(
+    socket_url: String,
+    token: String,
+    network_client: Arc<NetworkClient>,
+    user_id: i64,
+    rt: &Runtime,
+) -> Result<WsClient> {
+    let ws_client = WebSocketClient::new(socket_url, token);
+    // 断线重连后通过聊天记录接口补拉离线消息
+    ws_client.set_resume_source(network_client, user_id);
+    // 加载上次未发送成功的消息，连接建立后会自动补发
+    ws_client.set_outbox(Outbox::load(
+        config::account_data_dir(user_id).join("outbox.json"),
+    ));
+    // 监督任务在运行时中启动
+    let _guard = rt.enter();
+    ws_client.connect()?;
+    Ok(Arc::new(ws_client))
+}
+
+/// 监听 WebSocket 连接状态和连接事件，并同步到 `Store`
+fn bridge_connection_state(
+    rt: &Runtime,
+    ws_client: &WsClient,
+    weak_main: slint::Weak<Main>,
+) -> Vec<AbortHandle> {
+    let mut state_rx = ws_client.get_state_receiver();
+    let mut connection_events = ws_client.get_connection_event_receiver();
+
+    let weak_main_for_state = weak_main.clone();
+    let state_task = rt.spawn(async move {
+        loop {
+            let state = state_rx.borrow_and_update().clone();
+            let weak_main = weak_main_for_state.clone();
+            let _ = slint::invoke_from_event_loop(move || {
+                if let Some(window) = weak_main.upgrade() {
+                    let store = window.global::<Store>();
+                    let (status, reason) = match state {
+                        ConnectionState::Disconnected => (ConnectionStatus::Disconnected, None),
+                        ConnectionState::Connecting => (ConnectionStatus::Connecting, None),
+                        ConnectionState::Connected => (ConnectionStatus::Connected, None),
+                        ConnectionState::Reconnecting(attempt) => {
+                            store.set_reconnect_attempt(attempt as i32);
+                            (ConnectionStatus::Reconnecting, None)
+                        }
+                        ConnectionState::AuthFailed => (ConnectionStatus::AuthFailed, None),
+                        ConnectionState::Closed(reason) => (ConnectionStatus::Closed, Some(reason)),
+                    };
+                    store.set_connection_status(status);
+                    store.set_connection_reason(reason.unwrap_or_default().into());
+                }
+            });
+            if state_rx.changed().await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let event_task = rt.spawn(async move {
+        loop {
+            match connection_events.recv().await {
+                Ok(ConnectionEvent::RetryScheduled { attempt, delay }) => {
+                    println!(
+                        "[调试] 第{}次重连将在{}ms后开始",
+                        attempt,
+                        delay.as_millis()
+                    );
+                    let weak_main = weak_main.clone();
+                    let _ = slint::invoke_from_event_loop(move || {
+                        if let Some(window) = weak_main.upgrade() {
+                            let store = window.global::<Store>();
+                            store.set_reconnect_attempt(attempt as i32);
+                            store.set_reconnect_countdown(delay.as_secs_f64().ceil() as i32);
+                        }
+                    });
+                }
+                Ok(ConnectionEvent::Closed { code, reason }) => {
+                    println!("[调试] 连接被服务端关闭: {} {}", code, reason);
+                }
+                Ok(ConnectionEvent::PongReceived { rtt_ms: Some(rtt) }) => {
+                    println!("[调试] 心跳往返时间: {}ms", rtt);
+                }
+                Ok(event) => {
+                    println!("[调试] 连接事件: {:?}", event);
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+    vec![state_task.abort_handle(), event_task.abort_handle()]
+}
+
+/// 监听发出消息的投递状态，更新对应消息气泡
+fn bridge_message_status(
+    ws_client: &WsClient,
+    conversations: Rc<Conversations>,
+) -> Option<slint::JoinHandle<()>> {
+    let mut status_rx = ws_client.get_status_receiver();
+    // 消息模型只能在界面线程上访问
+    let result = slint::spawn_local(async move {
+        loop {
+            match status_rx.recv().await {
+                Ok(update) => {
+                    if let Some(error) = &update.error {
+                        println!("[错误] 消息{}发送失败: {}", update.id, error);
+                    }
+                    if let Some(server_id) = update.server_id {
+                        conversations.confirm_sent(&update.id, server_id);
+                    }
+                    conversations.update_visible(&update.id, |item| {
+                        // 确认帧可能乱序到达，状态只前进不回退
+                        let replace = MessageStatus::parse(&item.status)
+                            .is_none_or(|current| update.status.supersedes(current));
+                        if let Some(server_id) = update.server_id {
+                            item.server_id = server_id.to_string().into();
+                        }
+                        if replace {
+                            item.status = update.status.as_str().into();
+                        }
+                    });
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+    result
+        .inspect_err(|e| println!("[错误] 启动消息状态同步任务失败: {}", e))
+        .ok()
+}
+
+/// 监听服务端推送的各类事件；聊天消息和确认帧已有专门的通道处理
+fn bridge_server_events(
+    rt: &Runtime,
+    ws_client: &WsClient,
+    weak_main: slint::Weak<Main>,
+    user_id: i64,
+    peer_typing_generation: Arc<AtomicU64>,
+) -> AbortHandle {
+    let mut event_rx = ws_client.get_event_receiver();
+    let task = rt.spawn(async move {
+        loop {
+            match event_rx.recv().await {
+                Ok(ServerEvent::Message(_)) | Ok(ServerEvent::Ack(_)) => {}
+                Ok(ServerEvent::Typing(event)) => {
+                    if event.receiver_id != user_id || event.target_type != "person" {
+                        continue;
+                    }
+                    let weak_main = weak_main.clone();
+                    let generation = peer_typing_generation.clone();
+                    let _ = slint::invoke_from_event_loop(move || {
+                        if let Some(window) = weak_main.upgrade() {
+                            // 只显示当前单聊会话对方的输入状态
+                            let store = window.global::<Store>();
+                            if !store.get_current_chat_is_group()
+                                && store.get_current_chat() as i64 == event.sender_id
+                            {
+                                typing::show_peer_typing(&window, &generation, event.typing);
+                            }
+                        }
+                    });
+                }
+                Ok(ServerEvent::Presence(event)) => {
+                    // 自己的状态不显示在文件传输助手上
+                    if event.user_id == user_id {
+                        continue;
+                    }
+                    let weak_main = weak_main.clone();
+                    let _ = slint::invoke_from_event_loop(move || {
+                        if let Some(window) = weak_main.upgrade() {
+                            presence::handle_presence_event(&window, &event);
+                        }
+                    });
+                }
+                Ok(ServerEvent::ReadReceipt(receipt)) => {
+                    let weak_main = weak_main.clone();
+                    let _ = slint::invoke_from_event_loop(move || {
+                        if let Some(window) = weak_main.upgrade() {
+                            if receipt.reader_id == user_id {
+                                // 在其他设备上已读
+                                read_state::clear_unread(
+                                    &window,
+                                    ConversationKey::from_target(
+                                        receipt.chat_id,
+                                        &receipt.target_type,
+                                    ),
+                                );
+                            } else {
+                                read_state::apply_read_receipt(&window, &receipt);
+                            }
+                        }
+                    });
+                }
+                Ok(ServerEvent::Unknown(value)) => {
+                    println!("[调试] 收到暂不支持的事件: {}", value);
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    println!("[错误] 事件处理过慢，丢弃了{}个事件", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+    task.abort_handle()
+}
+
+/// token 刷新后更新本机保存的凭据和记住的账号
+fn persist_tokens(user_id: i64, token: &str, refresh_token: Option<&str>) {
+    credentials::update_tokens(user_id, token, refresh_token);
+    account::update_token(user_id, token);
+}
+
+/// 跟随登录状态的变化：token 刷新后保存新 token，并让 WebSocket 用它重新连接；
+/// 无法刷新时回到登录窗口，输入框中的草稿保存下来，重新登录后恢复
+fn bridge_auth_events(
+    rt: &Arc<Runtime>,
+    client: &NetworkClient,
+    ws_client: &WsClient,
+    weak_app: slint::Weak<Login>,
+    weak_main: slint::Weak<Main>,
+    session: Weak<RefCell<Option<MainSession>>>,
+    user_id: i64,
+) -> Option<slint::JoinHandle<()>> {
+    let mut auth_rx = client.subscribe_auth_events();
+    let rt = rt.clone();
+    let ws_client = ws_client.clone();
+    slint::spawn_local(async move {
+        loop {
+            match auth_rx.recv().await {
+                Ok(AuthEvent::Refreshed {
+                    token,
+                    refresh_token,
+                }) => {
+                    println!("[调试] token已刷新");
+                    persist_tokens(user_id, &token, refresh_token.as_deref());
+                    let _guard = rt.enter();
+                    ws_client.set_token(token);
+                }
+                Ok(AuthEvent::Expired) => {
+                    println!("[调试] 登录已过期，回到登录窗口");
+                    let Some(session) = session.upgrade() else {
+                        break;
+                    };
+                    // 退出登录会中止包括本任务在内的后台任务，放到任务结束后执行
+                    let weak_app = weak_app.clone();
+                    let weak_main = weak_main.clone();
+                    slint::Timer::single_shot(Duration::ZERO, move || {
+                        logout(&weak_app, &weak_main, &session, LogoutReason::Expired);
+                    });
+                    break;
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    })
+    .inspect_err(|e| println!("[错误] 启动登录状态同步任务失败: {}", e))
+    .ok()
+}
+
+/// 根据登录结果得到会话；服务器不可达时尝试离线登录之前登录过的账号。
+/// 失败时返回显示在登录窗口上的错误信息
+fn login_session(
+    client: &NetworkClient,
+    username: String,
+    password: String,
+    remember_me: bool,
+    result: api::ApiResult<api::LoginResponse>,
+) -> Result<Session, String> {
+    match result {
+        Ok(response) => {
+            if !response.success {
+                println!("登录失败: {}", response.message);
+                return Err(if response.message.is_empty() {
+                    "用户名或密码错误".to_string()
+                } else {
+                    response.message
+                });
+            }
+            println!("[调试] 登录成功");
+            let (Some(user_id), Some(token)) = (response.user_id, response.token) else {
+                println!("[错误] 登录响应缺少用户ID或token");
+                return Err("服务器响应异常，请稍后再试".to_string());
+            };
+            println!("[调试] 找到用户ID: {}", user_id);
+            // 记住账号，服务器不可达时可以离线登录
+            account::remember(&username, &password, user_id, &token);
+            // 勾选“记住我”时保存凭据，下次启动自动登录；否则清除之前保存的凭据
+            if remember_me {
+                let saved = credentials::SavedCredentials {
+                    user_id,
+                    username: username.clone(),
+                    token: token.clone(),
+                    refresh_token: response.refresh_token.clone(),
+                };
+                if let Err(e) = credentials::save(&saved) {
+                    println!("[错误] 保存登录凭据失败: {}", e);
+                }
+            } else {
+                credentials::clear();
+            }
+            Ok(Session {
+                user_id,
+                username,
+                token,
+            })
+        }
+        Err(e) => {
+            println!("网络错误: {}", e);
+            if !e.is_unreachable() {
+                return Err(login_error_message(&e));
+            }
+            // 服务器不可达时，离线登录之前登录过的账号，浏览本地缓存
+            let Some(account) = account::offline_login(&username, &password) else {
+                return Err(login_error_message(&e));
+            };
+            println!("[调试] 服务器不可达，离线登录: {}", account.user_id);
+            let token = account.token().unwrap_or_default();
+            client.set_token(token.clone());
+            Ok(Session {
+                user_id: account.user_id,
+                username,
+                token,
+            })
+        }
+    }
+}
+
+/// 启动时用保存的凭据自动登录：token 仍有效时直接打开主窗口，
+/// 失效时清除凭据并停留在登录窗口
+fn auto_login(
+    app: &Login,
+    request_slot: &RequestSlot,
+    context: LoginContext,
+    saved: credentials::SavedCredentials,
+) {
+    println!("[调试] 使用保存的凭据自动登录: {}", saved.username);
+    app.set_username(saved.username.clone().into());
+    app.set_remember_me(true);
+    app.set_error("".into());
+    app.set_busy(true);
+    context.client.set_token(saved.token.clone());
+    context
+        .client
+        .set_refresh_token(saved.refresh_token.clone());
+
+    let client = context.client.clone();
+    let weak_app = app.as_weak();
+    let rt = context.rt.clone();
+    request_slot.replace(api::request::spawn(
+        &rt,
+        async move { client.verify_token().await },
+        move |result| {
+            let Some(app) = weak_app.upgrade() else {
+                return;
+            };
+            app.set_busy(false);
+            match result {
+                Ok(()) => {}
+                // 服务器暂时不可达时沿用保存的 token，先离线浏览
+                Err(e) if e.is_unreachable() => {
+                    println!("[调试] 服务器不可达，使用保存的凭据离线登录: {}", e);
+                }
+                Err(ApiError::Unauthorized) => {
+                    println!("[调试] 保存的登录凭据已失效");
+                    credentials::clear();
+                    app.set_error("登录已过期，请重新登录".into());
+                    return;
+                }
+                Err(e) => {
+                    app.set_error(login_error_message(&e).into());
+                    return;
+                }
+            }
+            let LoginContext {
+                client,
+                rt,
+                socket_url,
+            } = context;
+            // 校验时 token 可能已经自动刷新
+            let token = client.get_token().unwrap_or(saved.token.clone());
+            if token != saved.token {
+                persist_tokens(saved.user_id, &token, client.get_refresh_token().as_deref());
+            }
+            let session = Session {
+                user_id: saved.user_id,
+                username: saved.username,
+                token,
+            };
+            if let Err(e) = open_main_window(&app, session, client, rt, &socket_url) {
+                println!("[错误] 打开主窗口失败: {}", e);
+                app.set_error("打开主窗口失败，请重试".into());
+            }
+        },
+    ));
+}
+
+/// 登录成功后打开主窗口所需的依赖
+struct LoginContext {
+    client: Arc<NetworkClient>,
+    rt: Arc<Runtime>,
+    socket_url: String,
+}
+
+/// 在后台执行登录请求，成功后打开主窗口，失败时在登录窗口显示原因
+fn submit_login<Fut>(
+    app: &Login,
+    request_slot: &RequestSlot,
+    context: LoginContext,
+    username: String,
+    password: String,
+    request: Fut,
+) where
+    Fut: std::future::Future<Output = api::ApiResult<api::LoginResponse>> + Send + 'static,
+{
+    app.set_error("".into());
+    app.set_busy(true);
+    let weak_app = app.as_weak();
+    let rt = context.rt.clone();
+    request_slot.replace(api::request::spawn(&rt, request, move |result| {
+        let Some(app) = weak_app.upgrade() else {
+            return;
+        };
+        app.set_busy(false);
+        let LoginContext {
+            client,
+            rt,
+            socket_url,
+        } = context;
+        let remember_me = app.get_remember_me();
+        let session = match login_session(&client, username, password, remember_me, result) {
+            Ok(session) => session,
+            Err(message) => {
+                app.set_error(message.into());
+                return;
+            }
+        };
+        if let Err(e) = open_main_window(&app, session, client, rt, &socket_url) {
+            println!("[错误] 打开主窗口失败: {}", e);
+            app.set_error("打开主窗口失败，请重试".into());
+        }
+    }));
+}
+
+/// 注册新账号并用同样的用户名和密码登录。注册失败的原因以登录失败的形式返回
+async fn register_and_login(
+    client: &NetworkClient,
+    request: api::RegisterRequest,
+) -> api::ApiResult<api::LoginResponse> {
+    let username = request.username.clone();
+    let password = request.password.clone();
+    let failed = |message: String| api::LoginResponse {
+        success: false,
+        message,
+        token: None,
+        refresh_token: None,
+        user_id: None,
+    };
+    match client.register(request).await {
+        Ok(response) if response.success => client.login(username, password).await,
+        Ok(response) if response.message.is_empty() => Ok(failed("注册失败，请稍后再试".into())),
+        Ok(response) => Ok(failed(response.message)),
+        Err(ApiError::Conflict) => Ok(failed("用户名已被注册".into())),
+        Err(e) => Err(e),
+    }
+}
+
+/// 登录失败时提示给用户的信息
+fn login_error_message(error: &ApiError) -> String {
+    match error {
+        ApiError::Unauthorized => "用户名或密码错误".to_string(),
+        ApiError::Network(e) if e.is_connect() => "无法连接服务器，请检查网络后重试".to_string(),
+        ApiError::Network(_) => "网络异常，请稍后重试".to_string(),
+        ApiError::Timeout => "连接服务器超时，请稍后重试".to_string(),
+        ApiError::RateLimited {
+            retry_after: Some(delay),
+        } => format!("登录尝试过于频繁，请{}秒后再试", delay.as_secs().max(1)),
+        ApiError::RateLimited { retry_after: None } => "登录尝试过于频繁，请稍后再试".to_string(),
+        ApiError::Server { description, .. } if !description.is_empty() => {
+            format!("服务器错误：{}", description)
+        }
+        ApiError::NotFound
+        | ApiError::Conflict
+        | ApiError::Server { .. }
+        | ApiError::Decode { .. }
+        | ApiError::File(_) => "服务器异常，请稍后再试".to_string(),
+    }
+}
+
+/// 聊天列表：文件传输助手、好友和群聊
+struct ChatList {
+    weak_main: slint::Weak<Main>,
+    client: Arc<NetworkClient>,
+    rt: Arc<Runtime>,
+    ws_client: WsClient,
+    cache: Arc<MessageCache>,
+    conversations: Rc<Conversations>,
+    search_panel: Rc<SearchPanel>,
+    groups: Rc<Groups>,
+    avatars: Rc<Avatars>,
+}
+
+impl ChatList {
+    /// 在后台获取好友列表、群聊和在线状态，完成后刷新聊天列表；失败时继续使用缓存
+    fn load(self: &Rc<Self>) {
+        println!("[调试] 正在获取好友列表...");
+        if let Some(window) = self.weak_main.upgrade() {
+            window.global::<Store>().set_friends_loading(true);
+        }
+        let client = self.client.clone();
+        let chat_list = self.clone();
+        // 聊天列表只在主窗口存在期间需要，不保留请求句柄
+        let _ = api::request::spawn_local(
+            &self.rt,
+            async move {
+                let friends = client.get_friend_list().await;
+                let presence = match friends {
+                    Ok(_) => client.get_presence_snapshot().await.unwrap_or_else(|e| {
+                        println!("获取在线状态失败: {}", e);
+                        Vec::new()
+                    }),
+                    Err(_) => Vec::new(),
+                };
+                let group_list = match friends {
+                    Ok(_) => load_groups(&client).await,
+                    Err(_) => None,
+                };
+                (friends, presence, group_list)
+            },
+            move |(friends, presence, group_list)| {
+                let Some(window) = chat_list.weak_main.upgrade() else {
+                    return;
+                };
+                window.global::<Store>().set_friends_loading(false);
+                let friends = match friends {
+                    Ok(friends) => friends,
+                    Err(e) => {
+                        println!("获取好友列表失败: {}，使用本地缓存", e);
+                        // token 已失效，提示重新登录
+                        if matches!(e, ApiError::Unauthorized) {
+                            window
+                                .global::<Store>()
+                                .set_connection_status(ConnectionStatus::AuthFailed);
+                        }
+                        return;
+                    }
+                };
+                println!("[调试] 收到好友列表，数量: {}", friends.len());
+                if let Err(e) = chat_list.cache.save_friends(&friends) {
+                    println!("[错误] 缓存好友列表失败: {}", e);
+                }
+                // 获取群聊失败时保留之前的群聊
+                if let Some(group_list) = group_list {
+                    if let Err(e) = chat_list.cache.save_groups(&group_list) {
+                        println!("[错误] 缓存群聊列表失败: {}", e);
+                    }
+                    chat_list.groups.set_groups(group_list);
+                }
+                chat_list.apply(&window, friends, presence);
+            },
+        );
+    }
+
+    /// 用好友列表和群聊重建聊天列表，保留已有会话的预览和未读数
+    fn apply(
+        &self,
+        window: &Main,
+        friends: Vec<api::FriendInfo>,
+        presence: Vec<api::PresenceInfo>,
+    ) {
+        let groups = self.groups.list();
+        let store = window.global::<Store>();
+        let user_id = store.get_user_info().id as i64;
+        // 重连后需要补拉这些会话的离线消息
+        let keys: Vec<ConversationKey> = friends
+            .iter()
+            .map(|friend| ConversationKey::person(friend.id))
+            .chain(groups.iter().map(|group| ConversationKey::group(group.id)))
+            .collect();
+        self.ws_client.track_chat(ConversationKey::person(user_id));
+        for key in keys {
+            self.ws_client.track_chat(key);
+        }
+
+        // 头像地址有变化时在后台加载，加载完成后替换占位头像
+        for friend in &friends {
+            self.avatars.update(friend.id, friend.avatar_url.as_deref());
+        }
+        for member in groups.iter().flat_map(|group| &group.members) {
+            self.avatars
+                .update(member.user_id, member.avatar_url.as_deref());
+        }
+
+        let existing = store.get_chat_items();
+        let own_avatar = self.avatars.get(user_id, &store.get_user_info().name);
+        let mut items = vec![ChatItem {
+            id: user_id as i32,
+            name: "文件传输助手".into(),
+            avatar: own_avatar.image,
+            avatar_initial: own_avatar.initial,
+            text: "".into(),
+            text_type: "text".into(),
+            time: "".into(),
+            presence: "".into(),
+            last_seen: "".into(),
+            unread: 0,
+            is_group: false,
+            members: ModelRc::default(),
+        }];
+        for friend in friends {
+            let avatar = self.avatars.get(friend.id, &friend.username);
+            let mut item = ChatItem {
+                id: friend.id as i32,
+                name: friend.username.into(),
+                avatar: avatar.image,
+                avatar_initial: avatar.initial,
+                text: "".into(),
+                text_type: "text".into(),
+                time: "".into(),
+                presence: "".into(),
+                last_seen: "".into(),
+                unread: 0,
+                is_group: false,
+                members: ModelRc::default(),
+            };
+            if let Some(info) = presence.iter().find(|info| info.user_id == friend.id) {
+                presence::apply_presence(
+                    &mut item,
+                    presence::parse_status(&info.status),
+                    info.last_seen,
+                );
+            }
+            items.push(item);
+        }
+        for group in &groups {
+            // 群头像由前几位成员名字的首字拼成
+            let members: Vec<slint::SharedString> = group
+                .members
+                .iter()
+                .filter_map(|member| member.username.chars().next())
+                .take(4)
+                .map(|initial| initial.to_string().into())
+                .collect();
+            items.push(ChatItem {
+                id: group.id as i32,
+                name: group.name.clone().into(),
+                // 群头像由界面用成员首字拼成
+                avatar: Image::default(),
+                avatar_initial: Default::default(),
+                text: "".into(),
+                text_type: "text".into(),
+                time: "".into(),
+                presence: "".into(),
+                last_seen: "".into(),
+                unread: 0,
+                is_group: true,
+                members: ModelRc::new(slint::VecModel::from(members)),
+            });
+        }
+        for item in items.iter_mut() {
+            let key = ConversationKey::of_chat(item);
+            if let Some(old) = existing
+                .iter()
+                .find(|old| ConversationKey::of_chat(old) == key)
+            {
+                item.unread = old.unread;
+                if presence.is_empty() {
+                    item.presence = old.presence;
+                    item.last_seen = old.last_seen;
+                }
+            }
+        }
+        store.set_chat_items(slint::ModelRc::new(slint::VecModel::from(items)));
+        // 按缓存中的最近消息恢复聊天列表
+        self.conversations.restore_previews();
+        self.search_panel.refresh_filters();
+    }
+}
+
+/// 获取我加入的群聊，列表中没有成员的群再单独获取成员。失败时返回 `None`
+async fn load_groups(client: &NetworkClient) -> Option<Vec<api::GroupInfo>> {
+    let mut groups = client
+        .get_groups()
+        .await
+        .inspect_err(|e| println!("[错误] 获取群聊列表失败: {}", e))
+        .ok()?;
+    for group in groups.iter_mut().filter(|group| group.members.is_empty()) {
+        match client.get_group(group.id).await {
+            Ok(info) => group.members = info.members,
+            Err(e) => println!("[错误] 获取群{}的成员失败: {}", group.id, e),
+        }
+    }
+    Some(groups)
+}
+
+/// 登录成功后创建主窗口，连接 WebSocket 并加载好友列表
+fn open_main_window(
+    app: &Login,
+    session: Session,
+    client: Arc<NetworkClient>,
+    rt: Arc<Runtime>,
+    socket_url: &str,
+) -> Result<()> {
+    let Session {
+        user_id,
+        username,
+        token,
+    } = session;
+    println!("[调试] 正在创建主窗口...");
+    let main_window = Main::new().unwrap();
+    let weak_main = main_window.as_weak();
+    // 初始化 WebSocket 客户端
+    let ws_client = create_ws_client(
+        socket_url.to_string(),
+        token.clone(),
+        client.clone(),
+        user_id,
+        &rt,
+    )
+    .inspect_err(|e| println!("[错误] 创建WebSocket客户端失败: {}", e))?;
+    // 打开本账号的本地缓存
+    let cache = Arc::new(
+        MessageCache::open(&config::account_data_dir(user_id).join("cache.db")).or_else(|e| {
+            println!("[错误] 打开本地缓存失败，改用内存缓存: {}", e);
+            MessageCache::in_memory()
+        })?,
+    );
+
+    // 克隆所有需要的变量
+    let weak_main_for_send = weak_main.clone();
+    let weak_main_for_handler = weak_main.clone();
+    let weak_main_for_chat = weak_main.clone();
+    let ws_client_for_receive = ws_client.clone();
+    let ws_client_for_send = ws_client.clone();
+    let username_for_send = username.clone();
+    let user_id_for_send = user_id;
+    let rt_for_send = rt.clone();
+    let ws_client_for_chat = ws_client.clone();
+    let typing_notifier = TypingNotifier::new(ws_client.clone(), rt.clone(), user_id);
+    let typing_notifier_for_chat = typing_notifier.clone();
+    let typing_notifier_for_send = typing_notifier.clone();
+    let peer_typing_generation = Arc::new(AtomicU64::new(0));
+    let peer_typing_generation_for_chat = peer_typing_generation.clone();
+    let typing_notifier_for_search = typing_notifier.clone();
+    let peer_typing_generation_for_search = peer_typing_generation.clone();
+    let read_tracker = ReadTracker::new(ws_client.clone(), rt.clone(), user_id);
+    // 好友、群成员和自己的头像
+    let avatars = Rc::new(Avatars::new(
+        weak_main.clone(),
+        user_id,
+        client.clone(),
+        rt.clone(),
+    ));
+    // 按会话保存收发的消息
+    let conversations = Rc::new(Conversations::new(
+        weak_main.clone(),
+        user_id,
+        read_tracker,
+        client.clone(),
+        rt.clone(),
+        cache.clone(),
+        avatars.clone(),
+    ));
+    let conversations_for_chat = conversations.clone();
+    let conversations_for_send = conversations.clone();
+    let conversations_for_receive = conversations.clone();
+    // 文件消息的上传和下载
+    let transfers = Rc::new(Transfers::new(
+        weak_main.clone(),
+        user_id,
+        username.clone(),
+        client.clone(),
+        rt.clone(),
+        ws_client.clone(),
+        conversations.clone(),
+    ));
+    let transfers_for_chat = transfers.clone();
+
+    // 设置聊天选择事件
+    weak_main_for_chat
+        .clone()
+        .upgrade()
+        .unwrap()
+        .global::<AppGlobal>()
+        .on_chat_selected(move |id, is_group| {
+            println!("[调试] 选中聊天: {}，群聊: {}", id, is_group);
+            typing_notifier_for_chat.stop();
+            if let Some(window) = weak_main_for_chat.upgrade() {
+                typing::show_peer_typing(&window, &peer_typing_generation_for_chat, false);
+            }
+
+            let mut outbox_entries = ws_client_for_chat.outbox_entries();
+            outbox_entries.extend(transfers_for_chat.pending_uploads());
+            conversations_for_chat.open(
+                ConversationKey {
+                    id: id as i64,
+                    is_group,
+                },
+                outbox_entries,
+            );
+        });
+
+    // 设置消息接收处理
+    let mut receiver = ws_client_for_receive.get_message_receiver();
+
+    // 在界面线程上接收消息，按会话分发
+    let receive_task = slint::spawn_local(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(message) => {
+                    println!("[调试] 收到新消息: {:?}", message);
+                    conversations_for_receive.receive(message);
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    println!("[错误] 消息处理过慢，丢弃了{}条消息", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    })
+    .inspect_err(|e| println!("[错误] 启动消息接收任务失败: {}", e))
+    .ok();
+
+    // 将连接状态同步到界面
+    let mut tasks = bridge_connection_state(&rt, &ws_client, weak_main.clone());
+    // 将发出消息的投递状态同步到界面
+    let status_task = bridge_message_status(&ws_client, conversations.clone());
+    // 处理聊天消息以外的服务端事件
+    tasks.push(bridge_server_events(
+        &rt,
+        &ws_client,
+        weak_main.clone(),
+        user_id,
+        peer_typing_generation,
+    ));
+    // 在 token 过期前主动刷新
+    let client_for_refresh = client.clone();
+    tasks.push(
+        rt.spawn(async move { client_for_refresh.keep_token_fresh().await })
+            .abort_handle(),
+    );
+
+    // 退出登录或切换账号时停止这些任务
+    let session = Rc::new(RefCell::new(Some(MainSession {
+        client: client.clone(),
+        rt: rt.clone(),
+        ws_client: ws_client.clone(),
+        tasks,
+        local_tasks: receive_task.into_iter().chain(status_task).collect(),
+    })));
+    // token 刷新和登录失效
+    let auth_task = bridge_auth_events(
+        &rt,
+        &client,
+        &ws_client,
+        app.as_weak(),
+        weak_main.clone(),
+        Rc::downgrade(&session),
+        user_id,
+    );
+    if let Some(session) = session.borrow_mut().as_mut() {
+        session.local_tasks.extend(auth_task);
+    }
+    let weak_app = app.as_weak();
+    let weak_main_for_logout = weak_main.clone();
+    let session_for_logout = session.clone();
+    main_window.global::<AppGlobal>().on_logout(move || {
+        logout(
+            &weak_app,
+            &weak_main_for_logout,
+            &session_for_logout,
+            LogoutReason::User,
+        );
+    });
+    let weak_app = app.as_weak();
+    let weak_main_for_switch = weak_main.clone();
+    main_window
+        .global::<AppGlobal>()
+        .on_switch_account(move || {
+            logout(
+                &weak_app,
+                &weak_main_for_switch,
+                &session,
+                LogoutReason::SwitchAccount,
+            );
+        });
+
+    // 修改文件下载目录
+    main_window
+        .global::<Store>()
+        .set_download_dir(settings::download_dir().display().to_string().into());
+    let weak_main_for_download_dir = weak_main.clone();
+    let rt_for_download_dir = rt.clone();
+    main_window
+        .global::<AppGlobal>()
+        .on_change_download_dir(move || {
+            let weak_main = weak_main_for_download_dir.clone();
+            let _ = api::request::spawn_local(
+                &rt_for_download_dir,
+                async {
+                    rfd::AsyncFileDialog::new()
+                        .set_title("选择文件下载位置")
+                        .set_directory(settings::download_dir())
+                        .pick_folder()
+                        .await
+                        .map(|folder| folder.path().to_path_buf())
+                },
+                move |folder| {
+                    let (Some(window), Some(folder)) = (weak_main.upgrade(), folder) else {
+                        return;
+                    };
+                    println!("[调试] 文件下载位置改为: {}", folder.display());
+                    window
+                        .global::<Store>()
+                        .set_download_dir(folder.display().to_string().into());
+                    settings::set_download_dir(folder);
+                },
+            );
+        });
+
+    // 长时间无输入时把自己标记为离开
+    let idle_monitor = IdleMonitor::new(
+        ws_client.clone(),
+        rt.clone(),
+        user_id,
+        PresenceConfig::default(),
+    );
+    let idle_monitor_for_typing = idle_monitor.clone();
+    main_window
+        .global::<AppGlobal>()
+        .on_user_activity(move || idle_monitor.activity());
+
+    // 输入时通知对方
+    let weak_main_for_typing = weak_main.clone();
+    main_window
+        .global::<AppGlobal>()
+        .on_input_edited(move |text| {
+            idle_monitor_for_typing.activity();
+            if let Some(window) = weak_main_for_typing.upgrade() {
+                // 群聊不发送输入状态
+                let store = window.global::<Store>();
+                if !store.get_current_chat_is_group() {
+                    typing_notifier.input_edited(store.get_current_chat() as i64, &text);
+                }
+            }
+        });
+
+    // 滚动到顶部时加载更早的消息
+    let conversations_for_history = conversations.clone();
+    main_window
+        .global::<AppGlobal>()
+        .on_load_older_messages(move || {
+            conversations_for_history.load_older();
+        });
+
+    // 本地消息搜索
+    let search_panel = Rc::new(SearchPanel::new(weak_main.clone(), user_id, cache.clone()));
+    let search_panel_for_change = search_panel.clone();
+    main_window
+        .global::<AppGlobal>()
+        .on_search_changed(move || {
+            search_panel_for_change.search();
+        });
+
+    // 点击搜索结果，打开会话并定位到这条消息
+    let weak_main_for_search = weak_main.clone();
+    let conversations_for_search = conversations.clone();
+    let ws_client_for_search = ws_client.clone();
+    let transfers_for_search = transfers.clone();
+    main_window
+        .global::<AppGlobal>()
+        .on_search_result_selected(move |result| {
+            println!(
+                "[调试] 打开搜索结果，会话: {}，消息: {}",
+                result.chat_id, result.message_id
+            );
+            typing_notifier_for_search.stop();
+            if let Some(window) = weak_main_for_search.upgrade() {
+                typing::show_peer_typing(&window, &peer_typing_generation_for_search, false);
+                let store = window.global::<Store>();
+                store.set_search_text(Default::default());
+                store.set_search_results(Default::default());
+            }
+
+            let mut outbox_entries = ws_client_for_search.outbox_entries();
+            outbox_entries.extend(transfers_for_search.pending_uploads());
+            let key = ConversationKey {
+                id: result.chat_id as i64,
+                is_group: result.is_group,
+            };
+            match result.message_id.parse::<i64>() {
+                Ok(message_id) => conversations_for_search.open_at(key, message_id, outbox_entries),
+                Err(_) => conversations_for_search.open(key, outbox_entries),
+            }
+        });
+
+    // 群聊：建群、查看成员、邀请和退出，完成后刷新聊天列表
+    let groups = Rc::new(Groups::new(
+        weak_main.clone(),
+        user_id,
+        client.clone(),
+        rt.clone(),
+    ));
+    let chat_list = Rc::new(ChatList {
+        weak_main: weak_main.clone(),
+        client: client.clone(),
+        rt: rt.clone(),
+        ws_client: ws_client.clone(),
+        cache: cache.clone(),
+        conversations: conversations.clone(),
+        search_panel: search_panel.clone(),
+        groups: groups.clone(),
+        avatars: avatars.clone(),
+    });
+    let groups_for_create = groups.clone();
+    main_window
+        .global::<AppGlobal>()
+        .on_open_create_group(move || groups_for_create.open_create());
+    let groups_for_info = groups.clone();
+    main_window
+        .global::<AppGlobal>()
+        .on_open_group_info(move || groups_for_info.open_info());
+    let groups_for_toggle = groups.clone();
+    main_window
+        .global::<AppGlobal>()
+        .on_toggle_group_candidate(move |index| groups_for_toggle.toggle(index as usize));
+    let groups_for_create = groups.clone();
+    let chat_list_for_create = chat_list.clone();
+    main_window
+        .global::<AppGlobal>()
+        .on_create_group(move |name| {
+            let chat_list = chat_list_for_create.clone();
+            groups_for_create.create(&name, move || chat_list.load());
+        });
+    let groups_for_invite = groups.clone();
+    let chat_list_for_invite = chat_list.clone();
+    main_window
+        .global::<AppGlobal>()
+        .on_invite_to_group(move || {
+            let chat_list = chat_list_for_invite.clone();
+            groups_for_invite.invite(move || chat_list.load());
+        });
+    let groups_for_leave = groups.clone();
+    let chat_list_for_leave = chat_list.clone();
+    main_window.global::<AppGlobal>().on_leave_group(move || {
+        let chat_list = chat_list_for_leave.clone();
+        groups_for_leave.leave(move || chat_list.load());
+    });
+
+    // 发送文件，点击文件卡片下载或打开
+    let transfers_for_send = transfers.clone();
+    main_window
+        .global::<AppGlobal>()
+        .on_send_file(move || transfers_for_send.pick_and_send());
+    let transfers_for_click = transfers.clone();
+    main_window
+        .global::<AppGlobal>()
+        .on_file_clicked(move |id| transfers_for_click.file_clicked(&id));
+
+    // 粘贴和发送图片，点击图片打开查看器
+    let transfers_for_paste = transfers.clone();
+    main_window
+        .global::<AppGlobal>()
+        .on_paste_image(move || transfers_for_paste.paste_image());
+    let images = conversations.images().clone();
+    let images_for_click = images.clone();
+    main_window
+        .global::<AppGlobal>()
+        .on_image_clicked(move |id| images_for_click.open_viewer(&id));
+    let images_for_close = images.clone();
+    main_window
+        .global::<AppGlobal>()
+        .on_close_viewer(move || images_for_close.close_viewer());
+    main_window
+        .global::<AppGlobal>()
+        .on_save_image(move || images.save_viewing());
+
+    // 手动重发失败的消息
+    let ws_client_for_resend = ws_client.clone();
+    let rt_for_resend = rt.clone();
+    main_window
+        .global::<AppGlobal>()
+        .on_resend_message(move |id| {
+            println!("[调试] 重发消息: {}", id);
+            // 文件没有上传成功时重新上传
+            if transfers.retry_upload(&id) {
+                return;
+            }
+            let _guard = rt_for_resend.enter();
+            ws_client_for_resend.resend_message(&id);
+        });
+
+    // 发送消息
+    weak_main_for_send
+        .clone()
+        .upgrade()
+        .unwrap()
+        .global::<AppGlobal>()
+        .on_send_message(move |message| {
+            println!("[调试] 发送消息: {}", message);
+            typing_notifier_for_send.stop();
+
+            if let Some(window) = weak_main_for_send.upgrade() {
+                let message_id = new_message_id();
+                let current = conversations_for_send.current(&window);
+                let chat_message = ChatMessage {
+                    username: username_for_send.to_string(),
+                    content: message.to_string(),
+                    message_type: "text".to_string(),
+                    sender_id: user_id_for_send,
+                    receiver_id: current.id,
+                    timestamp: chrono::Local::now().timestamp(),
+                    target_type: current.target_type().to_string(),
+                    direction: "send".to_string(),
+                    client_id: Some(message_id.clone()),
+                    message_id: None,
+                    file_path: None,
+                    file_name: None,
+                    file_size: None,
+                };
+                // 发送结果返回前先显示为发送中
+                conversations_for_send.send(&message_id, chat_message.clone());
+                // 由发件箱负责发送和失败重试。在界面线程上直接入队，保证按发送顺序进入发件箱
+                let _guard = rt_for_send.enter();
+                ws_client_for_send.enqueue_message(message_id, chat_message);
+            }
+            true
+        });
+
+    println!("[调试] 主窗口已创建");
+    let main_handler = WindowHandler::new(weak_main_for_handler);
+    println!("[调试] 正在初始化主窗口...");
+    main_handler.init_window().unwrap();
+    println!("[调试] 正在设置主窗口事件...");
+    main_handler.setup_window_events();
+    println!("[调试] 正在设置用户信息...");
+    let own_avatar = avatars.get(user_id, &username);
+    main_window.global::<Store>().set_user_info(UserInfo {
+        id: user_id as i32,
+        name: username.clone().into(),
+        avatar: own_avatar.image,
+        avatar_initial: own_avatar.initial,
+        signature: "".into(),
+        background: Image::default(),
+        phone: "".into(),
+        email: "".into(),
+    });
+    avatars.refresh_user(user_id);
+    // 先显示本地缓存的好友列表，再在后台从服务器刷新
+    let cached_friends = cache.friends().unwrap_or_else(|e| {
+        println!("[错误] 读取缓存的好友列表失败: {}", e);
+        Vec::new()
+    });
+    groups.set_groups(cache.groups().unwrap_or_else(|e| {
+        println!("[错误] 读取缓存的群聊列表失败: {}", e);
+        Vec::new()
+    }));
+    chat_list.apply(&main_window, cached_friends, Vec::new());
+    // 恢复上次关闭主窗口时没有发送的草稿
+    if let Some(draft) = draft::take(user_id) {
+        main_window
+            .global::<AppGlobal>()
+            .invoke_chat_selected(draft.chat_id as i32, draft.is_group);
+        main_window.global::<Store>().set_draft(draft.text.into());
+    }
+    chat_list.load();
+    main_window.show().unwrap();
+    app.window().hide().unwrap();
+    Ok(())
+}
+
+/// 回到登录窗口的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LogoutReason {
+    /// 用户退出登录
+    User,
+    /// 切换账号，清空登录窗口上的用户名
+    SwitchAccount,
+    /// token 失效且无法刷新
+    Expired,
+}
+
+/// 退出登录：断开连接、停止后台任务并清除 token，然后销毁主窗口回到登录窗口。
+/// 主窗口的 `Store` 随窗口一起释放，下次登录时从空白状态开始，只有输入框中的草稿会保存下来
+fn logout(
+    weak_app: &slint::Weak<Login>,
+    weak_main: &slint::Weak<Main>,
+    session: &RefCell<Option<MainSession>>,
+    reason: LogoutReason,
+) {
+    let Some(session) = session.take() else {
+        return;
+    };
+    println!("[调试] 退出登录: {:?}", reason);
+    // 退出后下次启动不再自动登录，失效的凭据也没有必要保留
+    credentials::clear();
+    session.close();
+    if let Some(window) = weak_main.upgrade() {
+        let store = window.global::<Store>();
+        let user_id = store.get_user_info().id as i64;
+        draft::save(
+            user_id,
+            &draft::Draft {
+                chat_id: store.get_current_chat() as i64,
+                is_group: store.get_current_chat_is_group(),
+                text: store.get_draft().to_string(),
+            },
+        );
+    }
+
+    if let Some(app) = weak_app.upgrade() {
+        if reason == LogoutReason::SwitchAccount {
+            app.set_username("".into());
+        }
+        app.set_password("".into());
+        app.set_confirm_password("".into());
+        app.set_error(match reason {
+            LogoutReason::Expired => "登录已过期，请重新登录".into(),
+            _ => "".into(),
+        });
+        app.set_busy(false);
+        app.set_registering(false);
+        if let Err(e) = app.show() {
+            println!("[错误] 显示登录窗口失败: {}", e);
+        }
+    }
+    // 当前仍在主窗口的回调中，等回调返回后再关闭主窗口；
+    // 之后不再有地方持有主窗口，它和其中的状态一起被销毁
+    let weak_main = weak_main.clone();
+    slint::Timer::single_shot(Duration::ZERO, move || {
+        if let Some(window) = weak_main.upgrade() {
+            if let Err(e) = window.hide() {
+                println!("[错误] 关闭主窗口失败: {}", e);
+            }
+        }
     });
-    Ok(Arc::new(Mutex::new(ws_client)))
 }
 
 fn main() -> Result<()> {
@@ -86,330 +1383,120 @@ fn main() -> Result<()> {
 
     let network_client = Arc::new(NetworkClient::new(server_url.clone()));
     let rt = Arc::new(Runtime::new()?);
+    let auto_login_context = LoginContext {
+        client: network_client.clone(),
+        rt: rt.clone(),
+        socket_url: socket_url.clone(),
+    };
 
     // 设置登录按钮点击事件
     let weak_app = app.as_weak();
     let client = network_client.clone();
+    let login_request = Rc::new(RequestSlot::default());
+    let login_request_for_login = login_request.clone();
+    let rt_for_login = rt.clone();
+    let socket_url_for_login = socket_url.clone();
     app.on_login(move || {
+        let Some(app) = weak_app.upgrade() else {
+            return;
+        };
+        if app.get_busy() {
+            return;
+        }
+        let username = app.get_username().trim().to_string();
+        let password = app.get_password().to_string();
+        if username.is_empty() {
+            app.set_error("请输入用户名".into());
+            return;
+        }
+        if password.is_empty() {
+            app.set_error("请输入密码".into());
+            return;
+        }
+
+        let request_client = client.clone();
+        let request_username = username.clone();
+        let request_password = password.clone();
+        submit_login(
+            &app,
+            &login_request_for_login,
+            LoginContext {
+                client: client.clone(),
+                rt: rt_for_login.clone(),
+                socket_url: socket_url_for_login.clone(),
+            },
+            username,
+            password,
+            async move {
+                request_client
+                    .login(request_username, request_password)
+                    .await
+            },
+        );
+    });
+
+    // 注册成功后直接登录
+    let weak_app = app.as_weak();
+    let client = network_client.clone();
+    let login_request_for_register = login_request.clone();
+    let login_request_for_auto = login_request.clone();
+    app.on_register(move || {
+        let Some(app) = weak_app.upgrade() else {
+            return;
+        };
+        if app.get_busy() {
+            return;
+        }
+        let form = account::Registration {
+            username: app.get_username().trim().to_string(),
+            password: app.get_password().to_string(),
+            confirm_password: app.get_confirm_password().to_string(),
+            email: app.get_email().trim().to_string(),
+            phone: app.get_phone().trim().to_string(),
+        };
+        if let Err(message) = account::validate_registration(&form) {
+            app.set_error(message.into());
+            return;
+        }
+
+        let request_client = client.clone();
+        let request = api::RegisterRequest {
+            username: form.username.clone(),
+            password: form.password.clone(),
+            email: (!form.email.is_empty()).then(|| form.email.clone()),
+            phone: (!form.phone.is_empty()).then(|| form.phone.clone()),
+        };
+        submit_login(
+            &app,
+            &login_request_for_register,
+            LoginContext {
+                client: client.clone(),
+                rt: rt.clone(),
+                socket_url: socket_url.clone(),
+            },
+            form.username,
+            form.password,
+            async move { register_and_login(&request_client, request).await },
+        );
+    });
+
+    app.on_password_strength(|password| account::password_strength(&password) as i32);
+
+    // 取消正在进行的登录
+    let weak_app = app.as_weak();
+    app.on_cancel_login(move || {
+        println!("[调试] 取消登录");
+        login_request.cancel();
         if let Some(app) = weak_app.upgrade() {
-            let username = app.get_username();
-            let password = app.get_password();
-            let username_clone = username.clone();
-            match client.login(username.clone().to_string(), password.to_string()) {
-                Ok(response) => {
-                    println!("[调试] 收到登录响应: {:?}", response);
-                    if response.success {
-                        println!("[调试] 登录成功");
-                        if let Some(user_id) = response.user_id {
-                            println!("[调试] 找到用户ID: {}", user_id);
-                            if let Some(app) = weak_app.upgrade() {
-                                println!("[调试] 正在创建主窗口...");
-                                let main_window = Main::new().unwrap();
-                                let weak_main = main_window.as_weak();
-                                // 初始化 WebSocket 客户端
-                                let token = response.token.unwrap();
-                                let ws_client = match create_ws_client(
-                                    socket_url.clone(),
-                                    token.clone(),
-                                    &rt,
-                                ) {
-                                    Ok(client) => client,
-                                    Err(e) => {
-                                        println!("[错误] 创建WebSocket客户端失败: {}", e);
-                                        return;
-                                    }
-                                };
-
-                                // 克隆所有需要的变量
-                                let weak_main_for_receive = weak_main.clone();
-                                let weak_main_for_send = weak_main.clone();
-                                let weak_main_for_handler = weak_main.clone();
-                                let weak_main_for_chat = weak_main.clone();
-                                let ws_client_for_receive = ws_client.clone();
-                                let ws_client_for_send = ws_client.clone();
-                                let username_for_send = username.clone();
-                                let user_id_for_receive = user_id;
-                                let user_id_for_send = user_id;
-                                let user_id_for_chat = user_id;
-                                let rt_for_send = rt.clone();
-                                let client_for_chat = client.clone();
-
-                                // 初始化空的消息列表
-                                if let Some(window) = weak_main_for_chat.upgrade() {
-                                    println!("[调试] 正在初始化消息列表");
-                                    let store = window.global::<Store>();
-                                    let message_items = VecModel::default();
-                                    store.set_message_items(slint::ModelRc::new(message_items));
-                                }
-
-                                // 设置聊天选择事件
-                                weak_main_for_chat
-                                    .clone()
-                                    .upgrade()
-                                    .unwrap()
-                                    .global::<AppGlobal>()
-                                    .on_chat_selected(move |id| {
-                                        println!("[调试] 选中聊天: {}", id);
-
-                                        match client_for_chat
-                                            .get_chat_history(id as i64, user_id_for_chat)
-                                        {
-                                            Ok(messages) => {
-                                                println!(
-                                                    "[调试] 收到聊天历史记录，数量: {}",
-                                                    messages.len()
-                                                );
-                                                // 获取现有的消息列表
-                                                if let Some(window) = weak_main_for_chat.upgrade() {
-                                                    let store = window.global::<Store>();
-                                                    let message_items = VecModel::default();
-                                                    // 添加历史消息
-                                                    for message in messages {
-                                                        println!(
-                                                            "[调试] 正在处理消息: {}",
-                                                            message.content
-                                                        );
-                                                        let message_item = MessageItem {
-                                                            text: message.content.into(),
-                                                            avatar: Image::from_rgb8(
-                                                                SharedPixelBuffer::new(640, 480),
-                                                            ),
-                                                            text_type: "text".into(),
-                                                            send_type: if message.sender_id
-                                                                == user_id_for_chat
-                                                            {
-                                                                "send".into()
-                                                            } else {
-                                                                "receive".into()
-                                                            },
-                                                            time: message
-                                                                .timestamp
-                                                                .to_string()
-                                                                .into(),
-                                                        };
-                                                        message_items.push(message_item);
-                                                    }
-                                                    println!(
-                                                        "[调试] 消息项已创建，数量: {}",
-                                                        message_items.row_count()
-                                                    );
-                                                    store.set_message_items(slint::ModelRc::new(
-                                                        message_items,
-                                                    ));
-                                                    store.set_current_chat(id);
-                                                    window.invoke_scroll_to_bottom();
-                                                }
-                                            }
-                                            Err(e) => {
-                                                println!("获取聊天历史记录失败: {}", e);
-                                            }
-                                        }
-                                    });
-
-                                // 设置消息接收处理
-                                let mut receiver = rt.block_on(async {
-                                    ws_client_for_receive.lock().await.get_message_receiver()
-                                });
-
-                                // 使用 tokio::spawn 处理消息接收
-                                rt.spawn(async move {
-                                    loop {
-                                        match receiver.recv().await {
-                                            Ok(message) => {
-                                                println!("[调试] 收到新消息: {:?}", message);
-                                                let weak_main_clone = weak_main_for_receive.clone();
-                                                let message_clone = message.clone();
-                                                let _ = slint::invoke_from_event_loop(move || {
-                                                    if let Some(window) = weak_main_clone.upgrade()
-                                                    {
-                                                        let store = window.global::<Store>();
-                                                        let existing_items =
-                                                            store.get_message_items();
-                                                        let message_items = VecModel::default();
-
-                                                        // 复制现有消息
-                                                        for i in 0..existing_items.row_count() {
-                                                            if let Some(item) =
-                                                                existing_items.row_data(i)
-                                                            {
-                                                                message_items.push(item);
-                                                            }
-                                                        }
-
-                                                        // 添加新消息
-                                                        let message_item = MessageItem {
-                                                            text: message_clone.content.into(),
-                                                            avatar: Image::from_rgb8(
-                                                                SharedPixelBuffer::new(640, 480),
-                                                            ),
-                                                            text_type: "text".into(),
-                                                            send_type: if message_clone.sender_id
-                                                                == user_id_for_receive
-                                                            {
-                                                                "send".into()
-                                                            } else {
-                                                                "receive".into()
-                                                            },
-                                                            time: message_clone
-                                                                .timestamp
-                                                                .to_string()
-                                                                .into(),
-                                                        };
-                                                        message_items.push(message_item);
-                                                        store.set_message_items(
-                                                            slint::ModelRc::new(message_items),
-                                                        );
-                                                        window.invoke_scroll_to_bottom();
-                                                    }
-                                                });
-                                            }
-                                            Err(e) => {
-                                                println!("[错误] 接收消息失败: {}", e);
-                                                break;
-                                            }
-                                        }
-                                    }
-                                });
-
-                                // 发送消息
-                                weak_main_for_send
-                                    .clone()
-                                    .upgrade()
-                                    .unwrap()
-                                    .global::<AppGlobal>()
-                                    .on_send_message(move |message| {
-                                        println!("[调试] 发送消息: {}", message);
-
-                                        if let Some(window) = weak_main_for_send.upgrade() {
-                                            let store = window.global::<Store>();
-                                            let existing_items = store.get_message_items();
-                                            let message_items = VecModel::default();
-
-                                            // 复制现有消息
-                                            for i in 0..existing_items.row_count() {
-                                                if let Some(item) = existing_items.row_data(i) {
-                                                    message_items.push(item);
-                                                }
-                                            }
-
-                                            let message_clone = message.clone();
-                                            // 添加新消息
-                                            let message_item = MessageItem {
-                                                text: message,
-                                                avatar: Image::from_rgb8(SharedPixelBuffer::new(
-                                                    640, 480,
-                                                )),
-                                                text_type: "text".into(),
-                                                send_type: "send".into(),
-                                                time: chrono::Local::now()
-                                                    .timestamp()
-                                                    .to_string()
-                                                    .into(),
-                                            };
-                                            message_items.push(message_item);
-                                            store.set_message_items(slint::ModelRc::new(
-                                                message_items,
-                                            ));
-
-                                            let current_id = store.get_current_chat();
-                                            let chat_message = ChatMessage {
-                                                username: username_for_send.to_string(),
-                                                content: message_clone.to_string(),
-                                                message_type: "text".to_string(),
-                                                sender_id: user_id_for_send,
-                                                receiver_id: current_id as i64,
-                                                timestamp: chrono::Local::now().timestamp(),
-                                                target_type: "person".to_string(),
-                                                direction: "send".to_string(),
-                                            };
-                                            let ws_client = ws_client_for_send.clone();
-                                            rt_for_send.spawn(async move {
-                                                if let Err(e) = ws_client
-                                                    .lock()
-                                                    .await
-                                                    .send_message(chat_message)
-                                                    .await
-                                                {
-                                                    println!("[错误] 发送消息失败: {}", e);
-                                                    return false;
-                                                }
-                                                true
-                                            });
-                                            window.invoke_scroll_to_bottom();
-                                        }
-                                        true
-                                    });
-
-                                println!("[调试] 主窗口已创建");
-                                let main_handler = WindowHandler::new(weak_main_for_handler);
-                                println!("[调试] 正在初始化主窗口...");
-                                main_handler.init_window().unwrap();
-                                println!("[调试] 正在设置主窗口事件...");
-                                main_handler.setup_window_events();
-                                println!("[调试] 正在设置用户信息...");
-                                main_window.global::<Store>().set_user_info(UserInfo {
-                                    id: user_id as i32,
-                                    name: username_clone,
-                                    avatar: Image::from_rgb8(SharedPixelBuffer::new(640, 480)),
-                                    signature: "".into(),
-                                    background: Image::from_rgb8(SharedPixelBuffer::new(640, 480)),
-                                    phone: "".into(),
-                                    email: "".into(),
-                                });
-                                println!("[调试] 正在获取好友列表...");
-                                match client.get_friend_list() {
-                                    Ok(friend_list) => {
-                                        println!(
-                                            "[调试] 收到好友列表，数量: {}",
-                                            friend_list.len()
-                                        );
-                                        let slint_friends = slint::VecModel::default();
-                                        slint_friends.push(ChatItem {
-                                            id: user_id as i32,
-                                            name: "文件传输助手".into(),
-                                            avatar: Image::from_rgb8(SharedPixelBuffer::new(
-                                                640, 480,
-                                            )),
-                                            text: "".into(),
-                                            text_type: "text".into(),
-                                            time: "".into(),
-                                        });
-                                        for friend in friend_list {
-                                            slint_friends.push(ChatItem {
-                                                id: friend.id as i32,
-                                                name: friend.username.into(),
-                                                avatar: Image::from_rgb8(SharedPixelBuffer::new(
-                                                    640, 480,
-                                                )),
-                                                text: "".into(),
-                                                text_type: "text".into(),
-                                                time: "".into(),
-                                            });
-                                        }
-                                        let model_rc = slint::ModelRc::new(slint_friends);
-                                        main_window.global::<Store>().set_chat_items(model_rc);
-                                    }
-                                    Err(e) => {
-                                        println!("获取好友列表失败: {}", e);
-                                    }
-                                }
-                                main_window.show().unwrap();
-                                app.window().hide().unwrap();
-                            }
-                        }
-                    } else {
-                        // TODO: 显示错误消息
-                        println!("登录失败: {}", response.message);
-                    }
-                }
-                Err(e) => {
-                    // TODO: 显示错误消息
-                    println!("网络错误: {}", e);
-                }
-            }
+            app.set_busy(false);
         }
     });
 
+    // 上次勾选了“记住我”时自动登录
+    if let Some(saved) = credentials::load() {
+        auto_login(&app, &login_request_for_auto, auto_login_context, saved);
+    }
+
     app.run()?;
     Ok(())
 }
@@ -0,0 +1,596 @@
+use crate::api::request::{self, RequestSlot};
+use crate::api::{NetworkClient, Progress};
+use crate::config::{self, MediaConfig};
+use crate::{Main, MessageItem, Store};
+use anyhow::{anyhow, Result};
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use sha2::{Digest, Sha256};
+use slint::{ComponentHandle, Model, Rgba8Pixel, SharedPixelBuffer, VecModel};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio::runtime::Runtime;
+
+/// 作为图片消息发送的文件类型
+const IMAGE_EXTENSIONS: [&str; 6] = ["jpg", "jpeg", "png", "gif", "webp", "bmp"];
+/// 内存中保留的已解码缩略图数量，超过后清空重新解码
+const MAX_DECODED_THUMBNAILS: usize = 200;
+
+/// 按扩展名判断是否为图片
+pub fn is_image(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .is_some_and(|extension| IMAGE_EXTENSIONS.contains(&extension.as_str()))
+}
+
+/// 有容量上限的磁盘缓存，文件名为键的哈希。超出上限时删除最久没有用到的文件
+pub struct DiskCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// 清理时避免与写入交错
+    lock: Mutex<()>,
+}
+
+impl DiskCache {
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        Self {
+            dir,
+            max_bytes,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// 键对应的文件路径，文件不一定存在
+    pub fn path(&self, key: &str) -> PathBuf {
+        let hash = Sha256::digest(key.as_bytes());
+        let name: String = hash.iter().map(|byte| format!("{:02x}", byte)).collect();
+        self.dir.join(name)
+    }
+
+    /// 读取缓存，命中时更新修改时间，作为最近使用的依据
+    pub fn get(&self, key: &str) -> Option<PathBuf> {
+        let path = self.path(key);
+        let file = std::fs::File::options().append(true).open(&path).ok()?;
+        let _ = file.set_modified(SystemTime::now());
+        Some(path)
+    }
+
+    pub fn insert(&self, key: &str, content: &[u8]) -> std::io::Result<PathBuf> {
+        let path = self.path(key);
+        {
+            let _guard = self.lock.lock().unwrap();
+            std::fs::create_dir_all(&self.dir)?;
+            std::fs::write(&path, content)?;
+        }
+        self.trim();
+        Ok(path)
+    }
+
+    /// 总大小超过上限时从最久没有用到的文件开始删除。
+    /// 原图直接下载到缓存目录，下载中的 `.part` 文件不计入也不删除
+    pub fn trim(&self) {
+        let _guard = self.lock.lock().unwrap();
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let path = entry.path();
+                if path
+                    .extension()
+                    .is_some_and(|extension| extension == "part")
+                {
+                    return None;
+                }
+                let metadata = entry.metadata().ok()?;
+                metadata.is_file().then(|| {
+                    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    (modified, metadata.len(), path)
+                })
+            })
+            .collect();
+        let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+        if total <= self.max_bytes {
+            return;
+        }
+        files.sort_by_key(|(modified, _, _)| *modified);
+        for (_, size, path) in files {
+            if total <= self.max_bytes {
+                break;
+            }
+            match std::fs::remove_file(&path) {
+                Ok(()) => total -= size,
+                Err(e) => println!("[错误] 清理缓存文件失败: {}", e),
+            }
+        }
+    }
+}
+
+/// 解码图片并按 EXIF 中的方向摆正，返回图片以及是否做了旋转
+fn open_oriented(path: &Path) -> Result<(DynamicImage, bool)> {
+    let mut decoder = ImageReader::open(path)?
+        .with_guessed_format()?
+        .into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    let rotated = orientation != image::metadata::Orientation::NoTransforms;
+    image.apply_orientation(orientation);
+    Ok((image, rotated))
+}
+
+fn encode_png(image: &DynamicImage) -> Result<Vec<u8>> {
+    let mut content = Vec::new();
+    image.write_to(&mut Cursor::new(&mut content), ImageFormat::Png)?;
+    Ok(content)
+}
+
+pub fn to_pixel_buffer(image: &DynamicImage) -> SharedPixelBuffer<Rgba8Pixel> {
+    let rgba = image.to_rgba8();
+    SharedPixelBuffer::clone_from_slice(rgba.as_raw(), rgba.width(), rgba.height())
+}
+
+/// 生成缩略图，返回 PNG 内容和解码好的像素
+fn thumbnail(image: &DynamicImage, size: u32) -> Result<(Vec<u8>, SharedPixelBuffer<Rgba8Pixel>)> {
+    let thumbnail = image.thumbnail(size, size);
+    Ok((encode_png(&thumbnail)?, to_pixel_buffer(&thumbnail)))
+}
+
+/// 解码图片文件用于显示，长边超过 `max_size` 时缩小
+fn decode_for_display(path: &Path, max_size: u32) -> Result<SharedPixelBuffer<Rgba8Pixel>> {
+    let (image, _) = open_oriented(path)?;
+    let image = if image.width() > max_size || image.height() > max_size {
+        image.resize(max_size, max_size, image::imageops::FilterType::Triangle)
+    } else {
+        image
+    };
+    Ok(to_pixel_buffer(&image))
+}
+
+/// 准备发送的图片
+pub struct PreparedImage {
+    /// 要上传的文件：需要按 EXIF 旋转时是摆正后另存的副本，否则是原文件
+    pub upload_path: PathBuf,
+    /// 缩略图的 PNG 内容，上传完成后写入缩略图缓存
+    pub thumbnail: Vec<u8>,
+    /// 发送过程中在气泡里显示的缩略图
+    pub preview: SharedPixelBuffer<Rgba8Pixel>,
+}
+
+/// 待发送图片的临时目录：粘贴的图片和摆正后的副本，上传完成后删除
+pub fn outgoing_dir(user_id: i64) -> PathBuf {
+    config::account_data_dir(user_id).join("outgoing")
+}
+
+/// 在临时目录中为一张待发送的图片分配位置，保留文件名
+pub fn outgoing_path(user_id: i64, file_name: &str) -> PathBuf {
+    outgoing_dir(user_id)
+        .join(uuid::Uuid::new_v4().to_string())
+        .join(file_name)
+}
+
+/// 把剪贴板中的图片存为 PNG
+pub fn save_rgba(width: u32, height: u32, pixels: Vec<u8>, path: &Path) -> Result<()> {
+    let image = image::RgbaImage::from_raw(width, height, pixels)
+        .ok_or_else(|| anyhow!("图片数据不完整"))?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    image.save_with_format(path, ImageFormat::Png)?;
+    Ok(())
+}
+
+/// 在本地摆正图片并生成缩略图。耗时较长，需要在后台线程调用
+pub fn prepare(path: &Path, user_id: i64, thumbnail_size: u32) -> Result<PreparedImage> {
+    let (image, rotated) = open_oriented(path)?;
+    let upload_path = if rotated {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "image".to_string());
+        // 需要旋转的基本都是照片，保持 JPEG 以免体积变大
+        let upload_path = outgoing_path(user_id, &format!("{}.jpg", stem));
+        if let Some(parent) = upload_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        DynamicImage::ImageRgb8(image.to_rgb8())
+            .save_with_format(&upload_path, ImageFormat::Jpeg)?;
+        upload_path
+    } else {
+        path.to_path_buf()
+    };
+    let (thumbnail, preview) = thumbnail(&image, thumbnail_size)?;
+    Ok(PreparedImage {
+        upload_path,
+        thumbnail,
+        preview,
+    })
+}
+
+/// 进行中的原图下载，等待者共享同一个结果
+type Download = Shared<BoxFuture<'static, Result<PathBuf, Arc<anyhow::Error>>>>;
+
+/// 原图缓存。原图下载到缓存目录中的 `.part` 文件，同一张原图同时只下载一次，
+/// 缩略图和查看器需要同一张原图时等待同一个下载
+struct Originals {
+    cache: Arc<DiskCache>,
+    network: Arc<NetworkClient>,
+    downloads: Arc<Mutex<HashMap<String, Download>>>,
+}
+
+impl Originals {
+    /// 缓存中的原图，没有时下载。所有等待者都取消后下载暂停，下次从断点继续
+    async fn fetch(&self, key: &str) -> Result<PathBuf> {
+        if let Some(path) = self.cache.get(key) {
+            return Ok(path);
+        }
+        let download = self
+            .downloads
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_insert_with(|| self.download(key.to_string()))
+            .clone();
+        download.await.map_err(|e| anyhow!("{:#}", e))
+    }
+
+    fn download(&self, key: String) -> Download {
+        let cache = self.cache.clone();
+        let network = self.network.clone();
+        let downloads = Arc::downgrade(&self.downloads);
+        async move {
+            let path = cache.path(&key);
+            let progress: Progress = Arc::new(|_, _| {});
+            let result = network.download_file(&key, &path, progress).await;
+            if let Some(downloads) = downloads.upgrade() {
+                downloads.lock().unwrap().remove(&key);
+            }
+            result.map_err(|e| Arc::new(anyhow::Error::from(e)))?;
+            cache.trim();
+            Ok(path)
+        }
+        .boxed()
+        .shared()
+    }
+}
+
+/// 图片消息的缩略图和大图查看。
+/// 缩略图先查磁盘缓存，没有时下载原图在本地生成；解码都在后台线程完成
+pub struct Images {
+    weak_main: slint::Weak<Main>,
+    rt: Arc<Runtime>,
+    config: MediaConfig,
+    /// 当前会话的消息列表，与 `Conversations` 共用
+    visible: Rc<VecModel<MessageItem>>,
+    thumbnails: Arc<DiskCache>,
+    originals: Arc<Originals>,
+    /// 已解码的缩略图，按文件路径
+    decoded: RefCell<HashMap<String, slint::Image>>,
+    loading: RefCell<HashSet<String>>,
+    viewer_request: RequestSlot,
+    /// 正在查看的原图和文件名，另存为时复制
+    viewing: RefCell<Option<(PathBuf, String)>>,
+}
+
+impl Images {
+    pub fn new(
+        weak_main: slint::Weak<Main>,
+        user_id: i64,
+        network: Arc<NetworkClient>,
+        rt: Arc<Runtime>,
+        visible: Rc<VecModel<MessageItem>>,
+    ) -> Self {
+        let config = MediaConfig::default();
+        let dir = config::account_data_dir(user_id);
+        Self {
+            weak_main,
+            rt,
+            visible,
+            thumbnails: Arc::new(DiskCache::new(
+                dir.join("thumbnails"),
+                config.thumbnail_cache_bytes,
+            )),
+            originals: Arc::new(Originals {
+                cache: Arc::new(DiskCache::new(dir.join("images"), config.image_cache_bytes)),
+                network,
+                downloads: Arc::new(Mutex::new(HashMap::new())),
+            }),
+            config,
+            decoded: RefCell::new(HashMap::new()),
+            loading: RefCell::new(HashSet::new()),
+            viewer_request: RequestSlot::default(),
+            viewing: RefCell::new(None),
+        }
+    }
+
+    pub fn thumbnail_size(&self) -> u32 {
+        self.config.thumbnail_size
+    }
+
+    /// 已经解码过的缩略图
+    pub fn cached(&self, file_path: &str) -> Option<slint::Image> {
+        self.decoded.borrow().get(file_path).cloned()
+    }
+
+    /// 自己发出的图片上传完成后，把本地生成的缩略图记到服务端路径下
+    pub fn remember_thumbnail(&self, file_path: &str, thumbnail: &[u8], preview: slint::Image) {
+        if let Err(e) = self.thumbnails.insert(file_path, thumbnail) {
+            println!("[错误] 写入缩略图缓存失败: {}", e);
+        }
+        self.remember_decoded(file_path, preview);
+    }
+
+    fn remember_decoded(&self, file_path: &str, image: slint::Image) {
+        let mut decoded = self.decoded.borrow_mut();
+        if decoded.len() >= MAX_DECODED_THUMBNAILS {
+            decoded.clear();
+        }
+        decoded.insert(file_path.to_string(), image);
+    }
+
+    /// 为当前会话中还没有缩略图的图片消息加载缩略图
+    pub fn load_visible(self: &Rc<Self>) {
+        let pending: HashSet<String> = self
+            .visible
+            .iter()
+            .filter(|item| {
+                item.text_type == "image"
+                    && !item.file_path.is_empty()
+                    && item.image.size().width == 0
+            })
+            .map(|item| item.file_path.to_string())
+            .collect();
+        for file_path in pending {
+            if let Some(image) = self.cached(&file_path) {
+                self.set_thumbnail(&file_path, image);
+            } else if self.loading.borrow_mut().insert(file_path.clone()) {
+                self.load_thumbnail(file_path);
+            }
+        }
+    }
+
+    fn load_thumbnail(self: &Rc<Self>, file_path: String) {
+        let thumbnails = self.thumbnails.clone();
+        let originals = self.originals.clone();
+        let size = self.config.thumbnail_size;
+        let key = file_path.clone();
+        let images = Rc::downgrade(self);
+        // 切换会话后继续加载，下次打开时直接使用
+        let _ = request::spawn_local(
+            &self.rt,
+            async move {
+                if let Some(path) = thumbnails.get(&key) {
+                    return tokio::task::spawn_blocking(move || decode_for_display(&path, size))
+                        .await?;
+                }
+                let original = originals.fetch(&key).await?;
+                tokio::task::spawn_blocking(move || {
+                    let (image, _) = open_oriented(&original)?;
+                    let (png, pixels) = thumbnail(&image, size)?;
+                    thumbnails.insert(&key, &png)?;
+                    Ok(pixels)
+                })
+                .await?
+            },
+            move |result: Result<SharedPixelBuffer<Rgba8Pixel>>| {
+                let Some(images) = images.upgrade() else {
+                    return;
+                };
+                images.loading.borrow_mut().remove(&file_path);
+                match result {
+                    Ok(pixels) => {
+                        let image = slint::Image::from_rgba8(pixels);
+                        images.remember_decoded(&file_path, image.clone());
+                        images.set_thumbnail(&file_path, image);
+                    }
+                    Err(e) => {
+                        println!("[错误] 加载缩略图失败: {}", e);
+                        images.update_items(&file_path, |item| item.file_state = "failed".into());
+                    }
+                }
+            },
+        );
+    }
+
+    fn set_thumbnail(&self, file_path: &str, image: slint::Image) {
+        self.update_items(file_path, |item| {
+            item.image = image.clone();
+            item.file_state = "".into();
+        });
+    }
+
+    fn update_items(&self, file_path: &str, update: impl Fn(&mut MessageItem)) {
+        for index in 0..self.visible.row_count() {
+            let Some(mut item) = self.visible.row_data(index) else {
+                continue;
+            };
+            if item.file_path.as_str() == file_path {
+                update(&mut item);
+                self.visible.set_row_data(index, item);
+            }
+        }
+    }
+
+    /// 打开大图查看器，原图不在缓存中时先下载
+    pub fn open_viewer(self: &Rc<Self>, id: &str) {
+        let Some(window) = self.weak_main.upgrade() else {
+            return;
+        };
+        let Some(item) = self.visible.iter().find(|item| item.id.as_str() == id) else {
+            return;
+        };
+        // 还在上传的图片没有服务端路径
+        if item.file_path.is_empty() {
+            return;
+        }
+        let store = window.global::<Store>();
+        store.set_viewer_image(item.image.clone());
+        store.set_viewer_loading(true);
+        store.set_viewer_message("".into());
+        store.set_viewer_visible(true);
+        self.viewing.replace(None);
+
+        let file_path = item.file_path.to_string();
+        let file_name = item.file_name.to_string();
+        let originals = self.originals.clone();
+        let max_size = self.config.viewer_max_size;
+        let images = Rc::downgrade(self);
+        self.viewer_request.replace(request::spawn_local(
+            &self.rt,
+            async move {
+                let original = originals.fetch(&file_path).await?;
+                let path = original.clone();
+                let pixels =
+                    tokio::task::spawn_blocking(move || decode_for_display(&path, max_size))
+                        .await??;
+                Ok::<_, anyhow::Error>((original, pixels))
+            },
+            move |result| {
+                let Some(images) = images.upgrade() else {
+                    return;
+                };
+                let Some(window) = images.weak_main.upgrade() else {
+                    return;
+                };
+                let store = window.global::<Store>();
+                store.set_viewer_loading(false);
+                match result {
+                    Ok((original, pixels)) => {
+                        store.set_viewer_image(slint::Image::from_rgba8(pixels));
+                        images.viewing.replace(Some((original, file_name)));
+                    }
+                    Err(e) => {
+                        println!("[错误] 加载原图失败: {}", e);
+                        store.set_viewer_message("原图加载失败".into());
+                    }
+                }
+            },
+        ));
+    }
+
+    /// 关闭查看器，停止加载原图
+    pub fn close_viewer(&self) {
+        self.viewer_request.cancel();
+        self.viewing.replace(None);
+        if let Some(window) = self.weak_main.upgrade() {
+            let store = window.global::<Store>();
+            store.set_viewer_visible(false);
+            store.set_viewer_image(Default::default());
+        }
+    }
+
+    /// 把正在查看的原图另存到用户选择的位置
+    pub fn save_viewing(self: &Rc<Self>) {
+        let Some((original, file_name)) = self.viewing.borrow().clone() else {
+            return;
+        };
+        let weak_main = self.weak_main.clone();
+        let _ = request::spawn_local(
+            &self.rt,
+            async move {
+                let Some(target) = rfd::AsyncFileDialog::new()
+                    .set_title("图片另存为")
+                    .set_file_name(file_name)
+                    .save_file()
+                    .await
+                else {
+                    return Ok(None);
+                };
+                let target = target.path().to_path_buf();
+                tokio::fs::copy(&original, &target)
+                    .await
+                    .map_err(|e| anyhow!("复制图片失败: {}", e))?;
+                Ok::<_, anyhow::Error>(Some(target))
+            },
+            move |result| {
+                let Some(window) = weak_main.upgrade() else {
+                    return;
+                };
+                let message = match result {
+                    Ok(Some(target)) => {
+                        println!("[调试] 图片已保存到: {}", target.display());
+                        "已保存".to_string()
+                    }
+                    Ok(None) => return,
+                    Err(e) => {
+                        println!("[错误] 保存图片失败: {}", e);
+                        "保存失败".to_string()
+                    }
+                };
+                window.global::<Store>().set_viewer_message(message.into());
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn temp_cache(max_bytes: u64) -> DiskCache {
+        let dir = std::env::temp_dir().join(format!("me_chat_cache_{}", uuid::Uuid::new_v4()));
+        DiskCache::new(dir, max_bytes)
+    }
+
+    /// 把文件的修改时间设为 `seconds` 秒之前
+    fn age(path: &Path, seconds: u64) {
+        let file = std::fs::File::options().append(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    #[test]
+    fn trim_removes_least_recently_used() {
+        let cache = temp_cache(25);
+        let old = cache.insert("old", &[0; 10]).unwrap();
+        let used = cache.insert("used", &[0; 10]).unwrap();
+        age(&old, 200);
+        age(&used, 300);
+        // 读取会更新最近使用时间
+        assert!(cache.get("used").is_some());
+        cache.insert("new", &[0; 10]).unwrap();
+
+        assert!(cache.get("old").is_none());
+        assert!(cache.get("used").is_some());
+        assert!(cache.get("new").is_some());
+        let _ = std::fs::remove_dir_all(&cache.dir);
+    }
+
+    #[test]
+    fn trim_keeps_everything_under_limit() {
+        let cache = temp_cache(100);
+        cache.insert("a", &[0; 40]).unwrap();
+        cache.insert("b", &[0; 40]).unwrap();
+        assert!(cache.get("a").is_some() && cache.get("b").is_some());
+        let _ = std::fs::remove_dir_all(&cache.dir);
+    }
+
+    #[test]
+    fn trim_leaves_partial_downloads() {
+        let cache = temp_cache(15);
+        let partial = crate::api::partial_path(&cache.path("downloading"));
+        std::fs::create_dir_all(&cache.dir).unwrap();
+        std::fs::write(&partial, [0; 100]).unwrap();
+        age(&partial, 1000);
+        cache.insert("a", &[0; 10]).unwrap();
+
+        // 下载中的文件不计入总大小，也不会被删除
+        assert!(partial.exists());
+        assert!(cache.get("a").is_some());
+        let _ = std::fs::remove_dir_all(&cache.dir);
+    }
+
+    #[test]
+    fn recognizes_image_extensions() {
+        assert!(is_image(Path::new("photo.JPG")));
+        assert!(is_image(Path::new("/tmp/a.webp")));
+        assert!(!is_image(Path::new("notes.txt")));
+        assert!(!is_image(Path::new("png")));
+    }
+}
@@ -0,0 +1,123 @@
+use crate::config::PresenceConfig;
+use crate::conversation;
+use crate::websocket::{ClientEvent, PresenceEvent, PresenceStatus};
+use crate::{ChatItem, Main, Store, WsClient};
+use slint::{ComponentHandle, Model, TimerMode};
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::runtime::Runtime;
+
+/// 根据窗口输入检测空闲，空闲超时后把自己的状态切换为离开，有输入时恢复在线
+pub struct IdleMonitor {
+    ws_client: WsClient,
+    rt: Arc<Runtime>,
+    user_id: i64,
+    config: PresenceConfig,
+    last_activity: Cell<Instant>,
+    away: Cell<bool>,
+    check_timer: slint::Timer,
+}
+
+impl IdleMonitor {
+    pub fn new(
+        ws_client: WsClient,
+        rt: Arc<Runtime>,
+        user_id: i64,
+        config: PresenceConfig,
+    ) -> Rc<Self> {
+        let monitor = Rc::new(Self {
+            ws_client,
+            rt,
+            user_id,
+            config,
+            last_activity: Cell::new(Instant::now()),
+            away: Cell::new(false),
+            check_timer: slint::Timer::default(),
+        });
+
+        let weak_monitor = Rc::downgrade(&monitor);
+        monitor.check_timer.start(
+            TimerMode::Repeated,
+            monitor.config.check_interval,
+            move || {
+                if let Some(monitor) = weak_monitor.upgrade() {
+                    monitor.check_idle();
+                }
+            },
+        );
+        monitor
+    }
+
+    /// 窗口上有鼠标或键盘输入时调用
+    pub fn activity(&self) {
+        self.last_activity.set(Instant::now());
+        if self.away.replace(false) {
+            println!("[调试] 检测到输入，恢复在线状态");
+            self.send(PresenceStatus::Online);
+        }
+    }
+
+    fn check_idle(&self) {
+        if self.away.get() || self.last_activity.get().elapsed() < self.config.idle_timeout {
+            return;
+        }
+        println!("[调试] 长时间无输入，切换为离开状态");
+        self.away.set(true);
+        self.send(PresenceStatus::Away);
+    }
+
+    fn send(&self, status: PresenceStatus) {
+        let event = ClientEvent::Presence(PresenceEvent {
+            user_id: self.user_id,
+            status,
+            last_seen: Some(chrono::Local::now().timestamp()),
+        });
+        let ws_client = self.ws_client.clone();
+        self.rt.spawn(async move {
+            if let Err(e) = ws_client.send_event(event).await {
+                println!("[调试] 发送在线状态失败: {}", e);
+            }
+        });
+    }
+}
+
+/// 把状态字符串转换为界面使用的值，无法识别的按离线处理
+pub fn parse_status(status: &str) -> PresenceStatus {
+    match status {
+        "online" => PresenceStatus::Online,
+        "away" => PresenceStatus::Away,
+        _ => PresenceStatus::Offline,
+    }
+}
+
+/// 生成“最后在线”文本：当天显示时间，更早显示日期
+pub fn format_last_seen(last_seen: Option<i64>) -> String {
+    match last_seen {
+        Some(ts) => format!("最后在线 {}", conversation::format_chat_time(ts)),
+        None => String::new(),
+    }
+}
+
+/// 更新聊天列表中对应好友的在线状态
+pub fn apply_presence(item: &mut ChatItem, status: PresenceStatus, last_seen: Option<i64>) {
+    item.presence = status.as_str().into();
+    item.last_seen = match status {
+        PresenceStatus::Offline => format_last_seen(last_seen).into(),
+        _ => "".into(),
+    };
+}
+
+/// 处理服务端推送的在线状态变化；群聊没有在线状态
+pub fn handle_presence_event(window: &Main, event: &PresenceEvent) {
+    let chat_items = window.global::<Store>().get_chat_items();
+    for i in 0..chat_items.row_count() {
+        if let Some(mut item) = chat_items.row_data(i) {
+            if !item.is_group && item.id as i64 == event.user_id {
+                apply_presence(&mut item, event.status, event.last_seen);
+                chat_items.set_row_data(i, item);
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use crate::conversation::ConversationKey;
+use crate::websocket::{ClientEvent, MessageStatus, ReadReceipt};
+use crate::{Main, Store, WsClient};
+use slint::{ComponentHandle, Model};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Runtime;
+
+/// 记录每个会话已读到的消息，已读位置前进时向服务端发送已读回执。
+/// 收消息的任务也会用到，因此使用 `Arc` 共享
+pub struct ReadTracker {
+    ws_client: WsClient,
+    rt: Arc<Runtime>,
+    user_id: i64,
+    /// 会话 -> 已读到的服务端消息ID
+    cursors: Mutex<HashMap<ConversationKey, i64>>,
+}
+
+impl ReadTracker {
+    pub fn new(ws_client: WsClient, rt: Arc<Runtime>, user_id: i64) -> Arc<Self> {
+        Arc::new(Self {
+            ws_client,
+            rt,
+            user_id,
+            cursors: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// 把会话的已读位置推进到 `message_id`，已经读过的位置不会重复发送回执
+    pub fn mark_read(&self, chat_id: i64, target_type: &str, message_id: i64) {
+        {
+            let mut cursors = self.cursors.lock().unwrap();
+            let key = ConversationKey::from_target(chat_id, target_type);
+            let cursor = cursors.entry(key).or_insert(0);
+            if message_id <= *cursor {
+                return;
+            }
+            *cursor = message_id;
+        }
+
+        let event = ClientEvent::ReadReceipt(ReadReceipt {
+            reader_id: self.user_id,
+            chat_id,
+            target_type: target_type.to_string(),
+            last_read_id: message_id,
+        });
+        let ws_client = self.ws_client.clone();
+        self.rt.spawn(async move {
+            if let Err(e) = ws_client.send_event(event).await {
+                println!("[调试] 发送已读回执失败: {}", e);
+            }
+        });
+    }
+}
+
+/// 会话收到一条未读消息
+pub fn increment_unread(window: &Main, key: ConversationKey) {
+    update_unread(window, key, |unread| unread + 1);
+}
+
+/// 打开会话后清空未读数
+pub fn clear_unread(window: &Main, key: ConversationKey) {
+    update_unread(window, key, |_| 0);
+}
+
+fn update_unread(window: &Main, key: ConversationKey, update: impl Fn(i32) -> i32) {
+    let store = window.global::<Store>();
+    let chat_items = store.get_chat_items();
+    let mut total = 0;
+    for i in 0..chat_items.row_count() {
+        let Some(mut item) = chat_items.row_data(i) else {
+            continue;
+        };
+        if ConversationKey::of_chat(&item) == key {
+            let unread = update(item.unread);
+            if unread != item.unread {
+                item.unread = unread;
+                chat_items.set_row_data(i, item.clone());
+            }
+        }
+        total += item.unread;
+    }
+    store.set_total_unread(total);
+}
+
+/// 对方的已读回执：把当前会话中自己发出、ID不大于已读位置的消息标记为已读
+pub fn apply_read_receipt(window: &Main, receipt: &ReadReceipt) {
+    let store = window.global::<Store>();
+    // 群聊没有逐条的已读状态
+    if receipt.target_type == "group"
+        || store.get_current_chat_is_group()
+        || receipt.chat_id != store.get_user_info().id as i64
+        || store.get_current_chat() as i64 != receipt.reader_id
+    {
+        return;
+    }
+    let message_items = store.get_message_items();
+    for i in 0..message_items.row_count() {
+        let Some(mut item) = message_items.row_data(i) else {
+            continue;
+        };
+        if item.send_type == "send"
+            && item
+                .server_id
+                .parse::<i64>()
+                .is_ok_and(|server_id| server_id <= receipt.last_read_id)
+            && item.status != MessageStatus::Read.as_str()
+        {
+            item.status = MessageStatus::Read.as_str().into();
+            message_items.set_row_data(i, item);
+        }
+    }
+}
@@ -0,0 +1,355 @@
+use crate::cache::{MessageCache, SearchFilter};
+use crate::conversation::{self, ConversationKey};
+use crate::{Main, SearchResult, Store};
+use slint::{ComponentHandle, Model, ModelRc, SharedString, VecModel};
+use std::cell::RefCell;
+use std::sync::Arc;
+
+/// 搜索结果最多显示的条数
+const MAX_RESULTS: u32 = 50;
+/// 摘要中命中位置前后保留的字数
+const SNIPPET_BEFORE: usize = 12;
+const SNIPPET_AFTER: usize = 40;
+
+/// 中日韩文字没有空格分词，按字切分
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF // 日文假名
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xAC00..=0xD7AF // 韩文
+        | 0xF900..=0xFAFF
+        | 0x20000..=0x2A6DF)
+}
+
+/// 按文字类型把文本拆成连续的片段：中日韩文字一段，字母数字组成的单词一段，其余字符丢弃
+fn runs(text: &str) -> Vec<(bool, Vec<char>)> {
+    let mut runs: Vec<(bool, Vec<char>)> = Vec::new();
+    for c in text.chars() {
+        let cjk = is_cjk(c);
+        if !cjk && !c.is_alphanumeric() {
+            runs.push((false, Vec::new()));
+            continue;
+        }
+        match runs.last_mut() {
+            Some((last_cjk, run)) if *last_cjk == cjk => run.push(c),
+            _ => runs.push((cjk, vec![c])),
+        }
+    }
+    runs.retain(|(_, run)| !run.is_empty());
+    runs
+}
+
+/// 生成写入全文索引的文本：中日韩文字同时写入单字和相邻两字，
+/// 其他文字按单词写入，由 FTS5 的 unicode61 分词器按空格切分
+pub fn segment(text: &str) -> String {
+    let mut tokens: Vec<String> = Vec::new();
+    for (cjk, run) in runs(text) {
+        if cjk {
+            tokens.extend(run.iter().map(|c| c.to_string()));
+            tokens.extend(run.windows(2).map(|pair| pair.iter().collect()));
+        } else {
+            tokens.push(run.iter().collect());
+        }
+    }
+    tokens.join(" ")
+}
+
+/// 把搜索词转换为 FTS5 查询：中日韩文字用相邻两字（只有一个字时用单字），
+/// 单词按前缀匹配，所有词都要命中。没有可搜索的内容时返回 `None`
+pub fn fts_query(text: &str) -> Option<String> {
+    let mut terms: Vec<String> = Vec::new();
+    for (cjk, run) in runs(text) {
+        if !cjk {
+            terms.push(format!("\"{}\"*", run.iter().collect::<String>()));
+        } else if run.len() == 1 {
+            terms.push(format!("\"{}\"", run[0]));
+        } else {
+            terms.extend(
+                run.windows(2)
+                    .map(|pair| format!("\"{}\"", pair.iter().collect::<String>())),
+            );
+        }
+    }
+    (!terms.is_empty()).then(|| terms.join(" AND "))
+}
+
+fn find_ignore_case(haystack: &[char], needle: &[char]) -> Option<usize> {
+    if needle.is_empty() || needle.len() > haystack.len() {
+        return None;
+    }
+    (0..=haystack.len() - needle.len()).find(|&start| {
+        haystack[start..start + needle.len()]
+            .iter()
+            .zip(needle)
+            .all(|(a, b)| a.to_lowercase().eq(b.to_lowercase()))
+    })
+}
+
+/// 截取命中位置附近的内容，返回命中前、命中部分和命中后三段，用于界面高亮
+pub fn snippet(content: &str, query: &str) -> (String, String, String) {
+    let content: Vec<char> = content.chars().filter(|c| *c != '\n').collect();
+    // 先找完整的搜索词，找不到再找其中的第一个片段
+    let mut candidates = vec![query.trim().chars().collect::<Vec<char>>()];
+    candidates.extend(runs(query).into_iter().map(|(_, run)| run));
+    let found = candidates.iter().find_map(|needle| {
+        find_ignore_case(&content, needle).map(|start| (start, start + needle.len()))
+    });
+    let Some((start, end)) = found else {
+        let text: String = content
+            .iter()
+            .take(SNIPPET_BEFORE + SNIPPET_AFTER)
+            .collect();
+        return (text, String::new(), String::new());
+    };
+
+    let before_start = start.saturating_sub(SNIPPET_BEFORE);
+    let after_end = (end + SNIPPET_AFTER).min(content.len());
+    let mut before: String = content[before_start..start].iter().collect();
+    if before_start > 0 {
+        before.insert(0, '…');
+    }
+    let mut after: String = content[end..after_end].iter().collect();
+    if after_end < content.len() {
+        after.push('…');
+    }
+    (before, content[start..end].iter().collect(), after)
+}
+
+/// 日期筛选项对应的起始时间，`None` 表示不限
+fn date_filter_start(index: i32) -> Option<i64> {
+    let today = chrono::Local::now()
+        .date_naive()
+        .and_hms_opt(0, 0, 0)?
+        .and_local_timezone(chrono::Local)
+        .single()?
+        .timestamp();
+    match index {
+        1 => Some(today),
+        2 => Some(today - 6 * 24 * 3600),
+        3 => Some(today - 29 * 24 * 3600),
+        _ => None,
+    }
+}
+
+/// 类型筛选项对应的消息类型
+fn type_filter(index: i32) -> Option<&'static str> {
+    match index {
+        1 => Some("text"),
+        2 => Some("file"),
+        3 => Some("image"),
+        _ => None,
+    }
+}
+
+/// 搜索面板：根据 `Store` 中的搜索词和筛选项查询本地缓存，并把结果写回界面
+pub struct SearchPanel {
+    weak_main: slint::Weak<Main>,
+    user_id: i64,
+    cache: Arc<MessageCache>,
+    /// 会话筛选项对应的会话，下标0为“全部会话”
+    conversations: RefCell<Vec<ConversationKey>>,
+    /// 发送者筛选项对应的用户ID，下标0为“全部发送者”
+    senders: RefCell<Vec<i64>>,
+}
+
+impl SearchPanel {
+    pub fn new(weak_main: slint::Weak<Main>, user_id: i64, cache: Arc<MessageCache>) -> Self {
+        Self {
+            weak_main,
+            user_id,
+            cache,
+            conversations: RefCell::new(Vec::new()),
+            senders: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// 根据当前的聊天列表生成会话和发送者筛选项
+    pub fn refresh_filters(&self) {
+        let Some(window) = self.weak_main.upgrade() else {
+            return;
+        };
+        let store = window.global::<Store>();
+        let chats: Vec<_> = store.get_chat_items().iter().collect();
+
+        let mut conversation_names: Vec<SharedString> = vec!["全部会话".into()];
+        let mut conversations = vec![ConversationKey::person(0)];
+        let mut sender_names: Vec<SharedString> = vec!["全部发送者".into(), "我".into()];
+        let mut senders = vec![0, self.user_id];
+        for chat in chats {
+            conversation_names.push(chat.name.clone());
+            conversations.push(ConversationKey::of_chat(&chat));
+            if !chat.is_group && chat.id as i64 != self.user_id {
+                sender_names.push(chat.name);
+                senders.push(chat.id as i64);
+            }
+        }
+        store.set_search_conversations(ModelRc::new(VecModel::from(conversation_names)));
+        store.set_search_senders(ModelRc::new(VecModel::from(sender_names)));
+        *self.conversations.borrow_mut() = conversations;
+        *self.senders.borrow_mut() = senders;
+    }
+
+    /// 搜索词或筛选项变化时重新搜索
+    pub fn search(&self) {
+        let Some(window) = self.weak_main.upgrade() else {
+            return;
+        };
+        let store = window.global::<Store>();
+        let text = store.get_search_text().to_string();
+        let Some(query) = fts_query(&text) else {
+            store.set_search_results(ModelRc::new(VecModel::<SearchResult>::default()));
+            return;
+        };
+
+        let index = |value: i32| (value > 0).then_some(value as usize);
+        let filter = SearchFilter {
+            query,
+            sender_id: index(store.get_search_sender_index())
+                .and_then(|i| self.senders.borrow().get(i).copied()),
+            conversation: index(store.get_search_conversation_index())
+                .and_then(|i| self.conversations.borrow().get(i).copied()),
+            since: date_filter_start(store.get_search_date_index()),
+            message_type: type_filter(store.get_search_type_index()).map(str::to_string),
+            limit: MAX_RESULTS,
+        };
+        let hits = self.cache.search(&filter).unwrap_or_else(|e| {
+            println!("[错误] 搜索失败: {}", e);
+            Vec::new()
+        });
+        println!("[调试] 搜索“{}”，结果数量: {}", text, hits.len());
+
+        let chat_items = store.get_chat_items();
+        let name_of = |key: ConversationKey| {
+            chat_items
+                .iter()
+                .find(|chat| ConversationKey::of_chat(chat) == key)
+                .map(|chat| chat.name)
+                .unwrap_or_default()
+        };
+        let results: Vec<SearchResult> = hits
+            .into_iter()
+            .map(|(key, message)| {
+                let (before, hit, after) = snippet(&message.content, &text);
+                SearchResult {
+                    chat_id: key.id as i32,
+                    is_group: key.is_group,
+                    message_id: message.id.to_string().into(),
+                    chat_name: name_of(key),
+                    sender: if message.sender_id == self.user_id {
+                        "我".into()
+                    } else {
+                        message.username.into()
+                    },
+                    time: conversation::format_chat_time(message.timestamp).into(),
+                    before: before.into(),
+                    hit: hit.into(),
+                    after: after.into(),
+                }
+            })
+            .collect();
+        store.set_search_results(ModelRc::new(VecModel::from(results)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segment_splits_cjk_into_chars_and_pairs() {
+        assert_eq!(segment("你好吗"), "你 好 吗 你好 好吗");
+        assert_eq!(segment("好"), "好");
+    }
+
+    #[test]
+    fn segment_keeps_words_whole() {
+        assert_eq!(segment("Hello, world!"), "Hello world");
+    }
+
+    #[test]
+    fn segment_mixed_text() {
+        assert_eq!(
+            segment("明天meeting改到3点"),
+            "明 天 明天 meeting 改 到 改到 3 点"
+        );
+        assert_eq!(segment("こんにちは"), "こ ん に ち は こん んに にち ちは");
+    }
+
+    #[test]
+    fn segment_empty_and_punctuation() {
+        assert_eq!(segment(""), "");
+        assert_eq!(segment("，。！?"), "");
+    }
+
+    #[test]
+    fn fts_query_uses_pairs_for_cjk() {
+        assert_eq!(
+            fts_query("你好吗").as_deref(),
+            Some("\"你好\" AND \"好吗\"")
+        );
+        assert_eq!(fts_query("好").as_deref(), Some("\"好\""));
+    }
+
+    #[test]
+    fn fts_query_prefix_matches_words() {
+        assert_eq!(fts_query("meet").as_deref(), Some("\"meet\"*"));
+        assert_eq!(
+            fts_query("明天 meet").as_deref(),
+            Some("\"明天\" AND \"meet\"*")
+        );
+    }
+
+    #[test]
+    fn fts_query_drops_quotes_and_operators() {
+        assert_eq!(
+            fts_query("\"a\" OR *").as_deref(),
+            Some("\"a\"* AND \"OR\"*")
+        );
+        assert_eq!(fts_query("  ，！ "), None);
+        assert_eq!(fts_query(""), None);
+    }
+
+    #[test]
+    fn snippet_highlights_cjk_match() {
+        assert_eq!(
+            snippet("我们明天开会吧", "明天"),
+            ("我们".to_string(), "明天".to_string(), "开会吧".to_string())
+        );
+    }
+
+    #[test]
+    fn snippet_ignores_case() {
+        assert_eq!(
+            snippet("See you at the Meeting", "meeting"),
+            (
+                "… you at the ".to_string(),
+                "Meeting".to_string(),
+                String::new()
+            )
+        );
+    }
+
+    #[test]
+    fn snippet_falls_back_to_first_matching_run() {
+        let (_, matched, _) = snippet("明天的meeting取消了", "meeting 取消");
+        assert_eq!(matched, "meeting");
+    }
+
+    #[test]
+    fn snippet_trims_long_content() {
+        let content = format!("{}关键词{}", "前".repeat(20), "后".repeat(60));
+        let (before, matched, after) = snippet(&content, "关键词");
+        assert_eq!(before, format!("…{}", "前".repeat(SNIPPET_BEFORE)));
+        assert_eq!(matched, "关键词");
+        assert_eq!(after, format!("{}…", "后".repeat(SNIPPET_AFTER)));
+    }
+
+    #[test]
+    fn snippet_without_match_shows_start() {
+        let content = "字".repeat(100);
+        let (before, matched, after) = snippet(&content, "没有");
+        assert_eq!(before.chars().count(), SNIPPET_BEFORE + SNIPPET_AFTER);
+        assert!(matched.is_empty() && after.is_empty());
+    }
+}
@@ -0,0 +1,56 @@
+use crate::config;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// 本机的应用设置，所有账号共用
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Settings {
+    /// 文件下载目录，为空时使用 `config::default_download_dir`
+    #[serde(default)]
+    pub download_dir: Option<PathBuf>,
+}
+
+fn settings_path() -> PathBuf {
+    config::data_dir().join("settings.json")
+}
+
+/// 读取设置，文件不存在或无法解析时使用默认值
+pub fn load() -> Settings {
+    let Ok(content) = std::fs::read_to_string(settings_path()) else {
+        return Settings::default();
+    };
+    serde_json::from_str(&content).unwrap_or_else(|e| {
+        println!("[错误] 解析设置文件失败，使用默认设置: {}", e);
+        Settings::default()
+    })
+}
+
+pub fn save(settings: &Settings) {
+    let path = settings_path();
+    let result = serde_json::to_string_pretty(settings)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, content)?;
+            Ok(())
+        });
+    if let Err(e) = result {
+        println!("[错误] 保存设置失败: {}", e);
+    }
+}
+
+/// 当前的文件下载目录
+pub fn download_dir() -> PathBuf {
+    load()
+        .download_dir
+        .unwrap_or_else(config::default_download_dir)
+}
+
+/// 修改文件下载目录
+pub fn set_download_dir(dir: PathBuf) {
+    let mut settings = load();
+    settings.download_dir = Some(dir);
+    save(&settings);
+}
@@ -0,0 +1,743 @@
+use crate::api::request::{self, Request};
+use crate::api::{self as network, NetworkClient, Progress};
+use crate::conversation::{ConversationKey, Conversations};
+use crate::media::{self, PreparedImage};
+use crate::websocket::{new_message_id, ChatMessage, MessageStatus, OutboxEntry};
+use crate::{config, settings, Main, MessageItem, WsClient};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+use tokio::sync::watch;
+
+/// 文件卡片上显示的传输状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileState {
+    /// 尚未下载
+    Remote,
+    Uploading,
+    Downloading,
+    /// 下载被暂停，已下载的部分保留在 `.part` 文件中
+    Paused,
+    /// 下载失败，再次点击从中断的位置继续
+    Failed,
+    Downloaded,
+}
+
+impl FileState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Remote => "",
+            Self::Uploading => "uploading",
+            Self::Downloading => "downloading",
+            Self::Paused => "paused",
+            Self::Failed => "failed",
+            Self::Downloaded => "downloaded",
+        }
+    }
+}
+
+/// 文件大小，例如 `512 B`、`1.2 MB`
+pub fn format_size(bytes: i64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes.max(0));
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// 为文件消息在下载目录中选一个还没有被占用的位置：优先用原文件名，
+/// 同名文件已存在时在文件名后加上消息ID和序号
+fn choose_path(message: &ChatMessage) -> PathBuf {
+    let dir = settings::download_dir();
+    // 文件名来自对方，只取最后一段，避免写到下载目录以外
+    let name = message
+        .file_name
+        .as_deref()
+        .and_then(|name| Path::new(name).file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "file".to_string());
+    let name = Path::new(&name);
+    let stem = name
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = name
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    let id = message.message_id.unwrap_or_default();
+    let free = |path: &PathBuf| !path.exists() && !network::partial_path(path).exists();
+    let path = dir.join(name);
+    if free(&path) {
+        return path;
+    }
+    (0..)
+        .map(|index| match index {
+            0 => dir.join(format!("{}_{}{}", stem, id, extension)),
+            _ => dir.join(format!("{}_{}_{}{}", stem, id, index, extension)),
+        })
+        .find(free)
+        .unwrap_or_default()
+}
+
+/// 文件消息下载到本机的位置，按消息记录在账号目录的 `downloads.json` 中。
+/// 只认记录下来的位置，不按文件名猜测下载目录中的文件是不是这个附件
+pub struct Downloads {
+    path: PathBuf,
+    saved: RefCell<HashMap<String, PathBuf>>,
+}
+
+impl Downloads {
+    pub fn load(user_id: i64) -> Self {
+        let path = config::account_data_dir(user_id).join("downloads.json");
+        let saved = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                println!("[错误] 解析下载记录失败: {}", e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        Self {
+            path,
+            saved: RefCell::new(saved),
+        }
+    }
+
+    /// 记录的键：服务端消息ID，还没有ID时用服务端文件路径
+    fn key(message: &ChatMessage) -> Option<String> {
+        message
+            .message_id
+            .map(|id| id.to_string())
+            .or_else(|| message.file_path.clone())
+    }
+
+    fn recorded(&self, message: &ChatMessage) -> Option<PathBuf> {
+        self.saved.borrow().get(&Self::key(message)?).cloned()
+    }
+
+    /// 已经下载完成的文件。记录的文件被删除或者换成了大小不同的文件时返回 `None`
+    pub fn downloaded(&self, message: &ChatMessage) -> Option<PathBuf> {
+        let path = self.recorded(message)?;
+        let metadata = std::fs::metadata(&path).ok()?;
+        let complete = metadata.is_file()
+            && message
+                .file_size
+                .is_none_or(|size| size == metadata.len() as i64);
+        complete.then_some(path)
+    }
+
+    /// 下载的目标位置：有未完成的下载时沿用原来的位置继续，否则选一个新位置并记录
+    pub fn destination(&self, message: &ChatMessage) -> PathBuf {
+        if let Some(path) = self.recorded(message) {
+            if !path.exists() {
+                return path;
+            }
+        }
+        let path = choose_path(message);
+        if let Some(key) = Self::key(message) {
+            self.saved.borrow_mut().insert(key, path.clone());
+            self.persist();
+        }
+        path
+    }
+
+    /// 根据记录的位置判断文件消息的下载状态和进度
+    pub fn state(&self, message: &ChatMessage) -> (FileState, f32) {
+        if self.downloaded(message).is_some() {
+            return (FileState::Downloaded, 1.0);
+        }
+        let Some(path) = self.recorded(message) else {
+            return (FileState::Remote, 0.0);
+        };
+        let size = message.file_size.unwrap_or(0).max(0) as u64;
+        match std::fs::metadata(network::partial_path(&path)) {
+            Ok(metadata) if size > 0 => (
+                FileState::Paused,
+                (metadata.len() as f32 / size as f32).min(1.0),
+            ),
+            _ => (FileState::Remote, 0.0),
+        }
+    }
+
+    fn persist(&self) {
+        let result = serde_json::to_string(&*self.saved.borrow())
+            .map_err(anyhow::Error::from)
+            .and_then(|content| {
+                if let Some(parent) = self.path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&self.path, content)?;
+                Ok(())
+            });
+        if let Err(e) = result {
+            println!("[错误] 保存下载记录失败: {}", e);
+        }
+    }
+}
+
+/// 用系统默认的程序打开文件
+fn open_path(path: &Path) {
+    #[cfg(target_os = "windows")]
+    let mut command = std::process::Command::new("explorer");
+    #[cfg(target_os = "macos")]
+    let mut command = std::process::Command::new("open");
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let mut command = std::process::Command::new("xdg-open");
+    if let Err(e) = command.arg(path).spawn() {
+        println!("[错误] 打开文件失败: {}", e);
+    }
+}
+
+/// 上传失败、等待用户重发的文件消息，保存在账号目录中，重启后仍可重发
+#[derive(Serialize, Deserialize)]
+struct FailedUpload {
+    id: String,
+    /// 待上传的文件，通常是发件目录中的副本
+    path: PathBuf,
+    message: ChatMessage,
+}
+
+fn failed_uploads_path(user_id: i64) -> PathBuf {
+    config::account_data_dir(user_id).join("failed_uploads.json")
+}
+
+/// 读取上次运行时上传失败的文件消息，文件已不存在的丢弃
+fn load_failed_uploads(user_id: i64) -> HashMap<String, (PathBuf, ChatMessage)> {
+    let uploads = match std::fs::read_to_string(failed_uploads_path(user_id)) {
+        Ok(content) => serde_json::from_str::<Vec<FailedUpload>>(&content).unwrap_or_else(|e| {
+            println!("[错误] 解析上传失败记录失败: {}", e);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    };
+    uploads
+        .into_iter()
+        .filter(|upload| {
+            let exists = upload.path.is_file();
+            if !exists {
+                println!("[错误] 待重发的文件已不存在: {}", upload.path.display());
+            }
+            exists
+        })
+        .map(|upload| (upload.id, (upload.path, upload.message)))
+        .collect()
+}
+
+/// 正在进行的上传或下载
+struct Transfer {
+    state: FileState,
+    request: Request,
+}
+
+/// 文件消息的上传和下载
+pub struct Transfers {
+    weak_main: slint::Weak<Main>,
+    user_id: i64,
+    username: String,
+    network: Arc<NetworkClient>,
+    rt: Arc<Runtime>,
+    ws_client: WsClient,
+    conversations: Rc<Conversations>,
+    /// 进行中的传输，按消息气泡的ID
+    active: RefCell<HashMap<String, Transfer>>,
+    /// 上传失败的文件消息和本地路径，点击重发时重新上传。
+    /// 每次变化都写入磁盘，文件复制到发件目录后不依赖用户原来的文件
+    failed_uploads: RefCell<HashMap<String, (PathBuf, ChatMessage)>>,
+    /// 上传中的图片在本地生成的缩略图，上传完成后写入缩略图缓存
+    thumbnails: RefCell<HashMap<String, (Vec<u8>, slint::Image)>>,
+}
+
+impl Transfers {
+    pub fn new(
+        weak_main: slint::Weak<Main>,
+        user_id: i64,
+        username: String,
+        network: Arc<NetworkClient>,
+        rt: Arc<Runtime>,
+        ws_client: WsClient,
+        conversations: Rc<Conversations>,
+    ) -> Self {
+        Self {
+            weak_main,
+            user_id,
+            username,
+            network,
+            rt,
+            ws_client,
+            conversations,
+            active: RefCell::new(HashMap::new()),
+            failed_uploads: RefCell::new(load_failed_uploads(user_id)),
+            thumbnails: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// 还没有进入发件箱的文件消息：上传中的显示为发送中，上传失败的显示为失败。
+    /// 重新打开会话时与发件箱中的消息一起恢复状态
+    pub fn pending_uploads(&self) -> Vec<OutboxEntry> {
+        let failed = self.failed_uploads.borrow();
+        let failed = failed.iter().map(|(id, (_, message))| OutboxEntry {
+            id: id.clone(),
+            message: message.clone(),
+            attempts: 0,
+            status: MessageStatus::Failed,
+        });
+        let active = self.active.borrow();
+        let uploading = active
+            .iter()
+            .filter(|(_, transfer)| transfer.state == FileState::Uploading)
+            .filter_map(|(id, _)| {
+                Some(OutboxEntry {
+                    id: id.clone(),
+                    message: self.conversations.find_message(id)?,
+                    attempts: 0,
+                    status: MessageStatus::Sending,
+                })
+            });
+        failed.chain(uploading).collect()
+    }
+
+    /// 选择文件并发送到当前会话。选择文件期间切换了会话也发送到原来的会话
+    pub fn pick_and_send(self: &Rc<Self>) {
+        let Some(window) = self.weak_main.upgrade() else {
+            return;
+        };
+        let key = self.conversations.current(&window);
+        let transfers = Rc::downgrade(self);
+        // 对话框关闭前不需要取消
+        let _ = request::spawn_local(
+            &self.rt,
+            async {
+                rfd::AsyncFileDialog::new()
+                    .set_title("选择要发送的文件")
+                    .pick_file()
+                    .await
+                    .map(|file| file.path().to_path_buf())
+            },
+            move |path| {
+                if let (Some(transfers), Some(path)) = (transfers.upgrade(), path) {
+                    transfers.send_file(key, path);
+                }
+            },
+        );
+    }
+
+    /// 剪贴板中有图片时存为 PNG 发送到当前会话，返回是否处理了这次粘贴
+    pub fn paste_image(self: &Rc<Self>) -> bool {
+        // 剪贴板中不是图片时交给输入框粘贴文本
+        let Ok(image) = arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_image())
+        else {
+            return false;
+        };
+        let Some(window) = self.weak_main.upgrade() else {
+            return false;
+        };
+        let key = self.conversations.current(&window);
+        let file_name = format!("截图_{}.png", chrono::Local::now().format("%Y%m%d_%H%M%S"));
+        let path = media::outgoing_path(self.user_id, &file_name);
+        println!("[调试] 粘贴图片: {}x{}", image.width, image.height);
+        match media::save_rgba(
+            image.width as u32,
+            image.height as u32,
+            image.bytes.into_owned(),
+            &path,
+        ) {
+            Ok(()) => self.send_image(key, path),
+            Err(e) => println!("[错误] 保存剪贴板图片失败: {}", e),
+        }
+        true
+    }
+
+    /// 图片作为图片消息发送，其他文件作为文件消息发送
+    fn send_file(self: &Rc<Self>, key: ConversationKey, path: PathBuf) {
+        if media::is_image(&path) {
+            self.send_image(key, path);
+        } else {
+            self.send_attachment(key, path, "file");
+        }
+    }
+
+    /// 先在后台摆正图片并生成缩略图，再按文件消息的流程上传；无法解码时按文件发送
+    fn send_image(self: &Rc<Self>, key: ConversationKey, path: PathBuf) {
+        let user_id = self.user_id;
+        let thumbnail_size = self.conversations.images().thumbnail_size();
+        let source = path.clone();
+        let transfers = Rc::downgrade(self);
+        let _ = request::spawn_local(
+            &self.rt,
+            async move {
+                tokio::task::spawn_blocking(move || {
+                    media::prepare(&source, user_id, thumbnail_size)
+                })
+                .await?
+            },
+            move |result: anyhow::Result<PreparedImage>| {
+                let Some(transfers) = transfers.upgrade() else {
+                    return;
+                };
+                let prepared = match result {
+                    Ok(prepared) => prepared,
+                    Err(e) => {
+                        println!("[错误] 处理图片失败，作为文件发送: {}", e);
+                        transfers.send_attachment(key, path, "file");
+                        return;
+                    }
+                };
+                let preview = slint::Image::from_rgba8(prepared.preview);
+                let Some(id) = transfers.send_attachment(key, prepared.upload_path, "image") else {
+                    return;
+                };
+                transfers.set_item(&id, |item| item.image = preview.clone());
+                transfers
+                    .thumbnails
+                    .borrow_mut()
+                    .insert(id, (prepared.thumbnail, preview));
+            },
+        );
+    }
+
+    /// 先上传文件，上传完成后把带有文件路径的消息放入发件箱，返回消息气泡的ID
+    fn send_attachment(
+        self: &Rc<Self>,
+        key: ConversationKey,
+        path: PathBuf,
+        message_type: &str,
+    ) -> Option<String> {
+        let file_size = match std::fs::metadata(&path) {
+            Ok(metadata) if metadata.is_file() => metadata.len() as i64,
+            Ok(_) => {
+                println!("[错误] 不能发送文件夹: {}", path.display());
+                return None;
+            }
+            Err(e) => {
+                println!("[错误] 读取文件信息失败: {}", e);
+                return None;
+            }
+        };
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "file".to_string());
+        println!("[调试] 发送文件: {}，大小: {}", file_name, file_size);
+        let id = new_message_id();
+        let message = ChatMessage {
+            username: self.username.clone(),
+            content: file_name.clone(),
+            message_type: message_type.to_string(),
+            sender_id: self.user_id,
+            receiver_id: key.id,
+            timestamp: chrono::Local::now().timestamp(),
+            target_type: key.target_type().to_string(),
+            direction: "send".to_string(),
+            client_id: Some(id.clone()),
+            message_id: None,
+            file_path: None,
+            file_name: Some(file_name),
+            file_size: Some(file_size),
+        };
+        self.conversations.send(&id, message.clone());
+        self.upload(id.clone(), path, message);
+        Some(id)
+    }
+
+    fn upload(self: &Rc<Self>, id: String, path: PathBuf, mut message: ChatMessage) {
+        self.set_item(&id, |item| {
+            item.status = MessageStatus::Sending.as_str().into();
+            item.file_state = FileState::Uploading.as_str().into();
+            item.progress = 0.0;
+        });
+        let progress = self.watch_progress(&id);
+        let network = self.network.clone();
+        let path_for_upload = path.clone();
+        let transfers = Rc::downgrade(self);
+        let id_for_done = id.clone();
+        let request = request::spawn_local(
+            &self.rt,
+            async move { network.upload_file(&path_for_upload, progress).await },
+            move |result| {
+                let Some(transfers) = transfers.upgrade() else {
+                    return;
+                };
+                let id = id_for_done;
+                transfers.active.borrow_mut().remove(&id);
+                match result {
+                    Ok(uploaded) => {
+                        message.file_path = Some(uploaded.file_path.clone());
+                        transfers.conversations.update_message(&id, |stored| {
+                            stored.file_path = Some(uploaded.file_path.clone());
+                        });
+                        let thumbnail = transfers.thumbnails.borrow_mut().remove(&id);
+                        transfers.set_item(&id, |item| {
+                            item.file_state = FileState::Remote.as_str().into();
+                            item.file_path = uploaded.file_path.as_str().into();
+                            if let Some((_, preview)) = &thumbnail {
+                                item.image = preview.clone();
+                            }
+                        });
+                        if let Some((content, preview)) = thumbnail {
+                            transfers.conversations.images().remember_thumbnail(
+                                &uploaded.file_path,
+                                &content,
+                                preview,
+                            );
+                        }
+                        // 粘贴的图片和摆正后的副本只为上传而存在
+                        if path.starts_with(media::outgoing_dir(transfers.user_id)) {
+                            if let Some(dir) = path.parent() {
+                                let _ = std::fs::remove_dir_all(dir);
+                            }
+                        }
+                        // 发件箱负责发送和失败重试。与文字消息一样在界面线程上入队，保持顺序
+                        let _guard = transfers.rt.enter();
+                        transfers.ws_client.enqueue_message(id, message);
+                    }
+                    Err(e) => {
+                        println!("[错误] 上传文件失败: {}", e);
+                        transfers.set_item(&id, |item| {
+                            item.status = MessageStatus::Failed.as_str().into();
+                            item.file_state = FileState::Remote.as_str().into();
+                        });
+                        transfers
+                            .failed_uploads
+                            .borrow_mut()
+                            .insert(id.clone(), (path.clone(), message));
+                        transfers.save_failed_uploads();
+                        transfers.stage_failed_upload(id, path);
+                    }
+                }
+            },
+        );
+        self.active.borrow_mut().insert(
+            id,
+            Transfer {
+                state: FileState::Uploading,
+                request,
+            },
+        );
+    }
+
+    /// 重新上传失败的文件消息，返回是否是上传失败的消息
+    pub fn retry_upload(self: &Rc<Self>, id: &str) -> bool {
+        let Some((path, message)) = self.failed_uploads.borrow_mut().remove(id) else {
+            return false;
+        };
+        self.save_failed_uploads();
+        println!("[调试] 重新上传文件: {}", path.display());
+        self.upload(id.to_string(), path, message);
+        true
+    }
+
+    fn save_failed_uploads(&self) {
+        let uploads: Vec<FailedUpload> = self
+            .failed_uploads
+            .borrow()
+            .iter()
+            .map(|(id, (path, message))| FailedUpload {
+                id: id.clone(),
+                path: path.clone(),
+                message: message.clone(),
+            })
+            .collect();
+        let path = failed_uploads_path(self.user_id);
+        let result = serde_json::to_string(&uploads)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&path, content)?;
+                Ok(())
+            });
+        if let Err(e) = result {
+            println!("[错误] 保存上传失败记录失败: {}", e);
+        }
+    }
+
+    /// 在后台把上传失败的文件复制到发件目录，之后重发使用副本，
+    /// 用户移动或删除原来的文件也不影响重发
+    fn stage_failed_upload(self: &Rc<Self>, id: String, path: PathBuf) {
+        if path.starts_with(media::outgoing_dir(self.user_id)) {
+            return;
+        }
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "file".to_string());
+        let staged = media::outgoing_path(self.user_id, &file_name);
+        let source = path.clone();
+        let target = staged.clone();
+        let transfers = Rc::downgrade(self);
+        let _ = request::spawn_local(
+            &self.rt,
+            async move {
+                if let Some(parent) = target.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                tokio::fs::copy(&source, &target).await?;
+                anyhow::Ok(())
+            },
+            move |result| {
+                let Some(transfers) = transfers.upgrade() else {
+                    return;
+                };
+                if let Err(e) = result {
+                    println!("[错误] 复制待重发的文件失败: {}", e);
+                    return;
+                }
+                let staged_in_place = match transfers.failed_uploads.borrow_mut().get_mut(&id) {
+                    Some((current, _)) if *current == path => {
+                        *current = staged.clone();
+                        true
+                    }
+                    _ => false,
+                };
+                if staged_in_place {
+                    transfers.save_failed_uploads();
+                } else if let Some(dir) = staged.parent() {
+                    // 复制期间已经重发，副本用不到了
+                    let _ = std::fs::remove_dir_all(dir);
+                }
+            },
+        );
+    }
+
+    /// 点击文件卡片：下载中则暂停，已下载则打开，否则开始或继续下载
+    pub fn file_clicked(self: &Rc<Self>, id: &str) {
+        let state = self.active.borrow().get(id).map(|transfer| transfer.state);
+        match state {
+            Some(FileState::Downloading) => {
+                if let Some(transfer) = self.active.borrow_mut().remove(id) {
+                    transfer.request.cancel();
+                }
+                println!("[调试] 暂停下载: {}", id);
+                self.set_item(id, |item| {
+                    item.file_state = FileState::Paused.as_str().into();
+                });
+                return;
+            }
+            // 上传完成前没有可下载的内容
+            Some(_) => return,
+            None => {}
+        }
+        let Some(message) = self.conversations.find_message(id) else {
+            return;
+        };
+        let Some(file_path) = message.file_path.clone() else {
+            return;
+        };
+        let downloads = self.conversations.downloads();
+        if let Some(path) = downloads.downloaded(&message) {
+            open_path(&path);
+            return;
+        }
+        self.download(id.to_string(), file_path, downloads.destination(&message));
+    }
+
+    fn download(self: &Rc<Self>, id: String, file_path: String, dest: PathBuf) {
+        println!("[调试] 下载文件到: {}", dest.display());
+        self.set_item(&id, |item| {
+            item.file_state = FileState::Downloading.as_str().into();
+        });
+        let progress = self.watch_progress(&id);
+        let network = self.network.clone();
+        let transfers = Rc::downgrade(self);
+        let id_for_done = id.clone();
+        let request = request::spawn_local(
+            &self.rt,
+            async move { network.download_file(&file_path, &dest, progress).await },
+            move |result| {
+                let Some(transfers) = transfers.upgrade() else {
+                    return;
+                };
+                transfers.active.borrow_mut().remove(&id_for_done);
+                let state = match result {
+                    Ok(()) => FileState::Downloaded,
+                    Err(e) => {
+                        println!("[错误] 下载文件失败: {}", e);
+                        FileState::Failed
+                    }
+                };
+                transfers.set_item(&id_for_done, |item| {
+                    item.file_state = state.as_str().into();
+                    if state == FileState::Downloaded {
+                        item.progress = 1.0;
+                    }
+                });
+            },
+        );
+        self.active.borrow_mut().insert(
+            id,
+            Transfer {
+                state: FileState::Downloading,
+                request,
+            },
+        );
+    }
+
+    /// 更新当前会话中的文件卡片，不在当前会话时忽略
+    fn set_item(&self, id: &str, update: impl FnOnce(&mut MessageItem)) {
+        self.conversations.update_visible(id, update);
+    }
+
+    /// 把传输进度同步到文件卡片。进度在后台线程上报，界面只取最新的值，
+    /// 传输结束、回调被释放后同步任务随之结束
+    fn watch_progress(self: &Rc<Self>, id: &str) -> Progress {
+        let (progress_tx, mut progress_rx) = watch::channel((0u64, 0u64));
+        let transfers: Weak<Self> = Rc::downgrade(self);
+        let id = id.to_string();
+        let result = slint::spawn_local(async move {
+            while progress_rx.changed().await.is_ok() {
+                let (done, total) = *progress_rx.borrow_and_update();
+                let Some(transfers) = transfers.upgrade() else {
+                    return;
+                };
+                // 重新打开会话后气泡是重建的，顺便恢复进行中的状态
+                let Some(state) = transfers.active.borrow().get(&id).map(|t| t.state) else {
+                    continue;
+                };
+                transfers.set_item(&id, |item| {
+                    item.file_state = state.as_str().into();
+                    if total > 0 {
+                        item.progress = (done as f32 / total as f32).min(1.0);
+                    }
+                });
+            }
+        });
+        if let Err(e) = result {
+            println!("[错误] 启动进度同步失败: {}", e);
+        }
+        Arc::new(move |done, total| {
+            progress_tx.send_replace((done, total));
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_size_picks_unit() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1024), "1.0 KB");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GB");
+    }
+
+    #[test]
+    fn format_size_clamps_out_of_range() {
+        assert_eq!(format_size(-1), "0 B");
+        assert_eq!(format_size(2048 * 1024 * 1024 * 1024 * 1024), "2048.0 TB");
+    }
+}
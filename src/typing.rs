@@ -0,0 +1,114 @@
+use crate::websocket::{ClientEvent, TypingEvent};
+use crate::{Main, Store, WsClient};
+use slint::{ComponentHandle, TimerMode};
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+
+/// 持续输入时，两次“正在输入”事件之间的最小间隔
+const TYPING_THROTTLE: Duration = Duration::from_secs(3);
+/// 停止编辑多久后发送“停止输入”
+const TYPING_IDLE: Duration = Duration::from_secs(5);
+/// 对方的“正在输入”提示在没有收到停止事件时的最长显示时间
+const PEER_TYPING_TIMEOUT: Duration = Duration::from_secs(6);
+
+/// 根据输入框的编辑情况，节流发送“正在输入/停止输入”事件
+pub struct TypingNotifier {
+    ws_client: WsClient,
+    rt: Arc<Runtime>,
+    user_id: i64,
+    /// 正在通知的会话，以及最近一次发送“正在输入”的时间
+    active: Cell<Option<(i64, Instant)>>,
+    idle_timer: slint::Timer,
+}
+
+impl TypingNotifier {
+    pub fn new(ws_client: WsClient, rt: Arc<Runtime>, user_id: i64) -> Rc<Self> {
+        Rc::new(Self {
+            ws_client,
+            rt,
+            user_id,
+            active: Cell::new(None),
+            idle_timer: slint::Timer::default(),
+        })
+    }
+
+    /// 输入框内容变化时调用
+    pub fn input_edited(self: &Rc<Self>, chat_id: i64, text: &str) {
+        if text.is_empty() {
+            self.stop();
+            return;
+        }
+
+        let should_notify = match self.active.get() {
+            Some((active_chat, sent_at)) if active_chat == chat_id => {
+                sent_at.elapsed() >= TYPING_THROTTLE
+            }
+            Some((active_chat, _)) => {
+                // 切换了会话，先通知原会话停止输入
+                self.send(active_chat, false);
+                true
+            }
+            None => true,
+        };
+        if should_notify {
+            self.send(chat_id, true);
+            self.active.set(Some((chat_id, Instant::now())));
+        }
+
+        let notifier = Rc::downgrade(self);
+        self.idle_timer
+            .start(TimerMode::SingleShot, TYPING_IDLE, move || {
+                if let Some(notifier) = notifier.upgrade() {
+                    notifier.stop();
+                }
+            });
+    }
+
+    /// 发送消息、清空输入框或切换会话时调用
+    pub fn stop(&self) {
+        self.idle_timer.stop();
+        if let Some((chat_id, _)) = self.active.take() {
+            self.send(chat_id, false);
+        }
+    }
+
+    fn send(&self, chat_id: i64, typing: bool) {
+        let event = ClientEvent::Typing(TypingEvent {
+            sender_id: self.user_id,
+            receiver_id: chat_id,
+            target_type: "person".to_string(),
+            typing,
+        });
+        let ws_client = self.ws_client.clone();
+        self.rt.spawn(async move {
+            if let Err(e) = ws_client.send_event(event).await {
+                println!("[调试] 发送输入状态失败: {}", e);
+            }
+        });
+    }
+}
+
+/// 显示或隐藏“对方正在输入”。每次调用都会重新计时，
+/// 超过 `PEER_TYPING_TIMEOUT` 仍没有新的输入事件时自动隐藏
+pub fn show_peer_typing(window: &Main, generation: &Arc<AtomicU64>, typing: bool) {
+    window.global::<Store>().set_peer_typing(typing);
+    let current = generation.fetch_add(1, Ordering::SeqCst) + 1;
+    if !typing {
+        return;
+    }
+
+    let weak_main = window.as_weak();
+    let generation = generation.clone();
+    slint::Timer::single_shot(PEER_TYPING_TIMEOUT, move || {
+        if generation.load(Ordering::SeqCst) != current {
+            return;
+        }
+        if let Some(window) = weak_main.upgrade() {
+            window.global::<Store>().set_peer_typing(false);
+        }
+    });
+}
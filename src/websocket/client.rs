@@ -0,0 +1,720 @@
+use super::{
+    ChatMessage, ClientEvent, MessageAck, MessageStatus, MessageStatusUpdate, Outbox, OutboxEntry,
+    ServerEvent,
+};
+use crate::api::{ApiError, ApiResult, NetworkClient, HISTORY_PAGE_SIZE};
+use crate::config::WebSocketConfig;
+use crate::conversation::ConversationKey;
+use anyhow::Result;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio::sync::watch;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::http;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Error as WsError;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use url::Url;
+
+type WsStream = WebSocketStream<tokio_tungstenite::MaybeTlsStream<TcpStream>>;
+type WsWrite = SplitSink<WsStream, Message>;
+type WsRead = SplitStream<WsStream>;
+
+/// WebSocket 连接状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    Disconnected,
+    Connecting,
+    Connected,
+    /// 正在进行第 N 次重连
+    Reconnecting(u32),
+    /// 握手被服务端以 401/403 拒绝，不再自动重连
+    AuthFailed,
+    /// 服务端主动关闭了连接，附带关闭原因
+    Closed(String),
+}
+
+/// 连接层事件，供上层观察连接的健康状况
+#[derive(Debug, Clone)]
+pub enum ConnectionEvent {
+    Connected,
+    Disconnected,
+    /// 收到服务端 Ping（已自动回复 Pong）
+    PingReceived,
+    /// 收到 Pong，附带距离上一次发送 Ping 的往返时间（毫秒）
+    PongReceived {
+        rtt_ms: Option<u64>,
+    },
+    /// 收到服务端的 Close 帧
+    Closed {
+        code: u16,
+        reason: String,
+    },
+    /// 超过心跳超时时间未收到任何数据，连接被判定为失效
+    HeartbeatTimeout,
+    /// 已安排第 `attempt` 次重连，将在 `delay` 后开始
+    RetryScheduled {
+        attempt: u32,
+        delay: Duration,
+    },
+}
+
+/// 断线重连后用于补拉离线消息的数据源
+struct ResumeSource {
+    network: Arc<NetworkClient>,
+    user_id: i64,
+}
+
+/// 连接监督任务与客户端共享的状态
+struct Shared {
+    url: String,
+    /// 握手使用的 token；刷新后监督任务会用新 token 重新连接
+    token_tx: watch::Sender<String>,
+    config: WebSocketConfig,
+    state_tx: watch::Sender<ConnectionState>,
+    message_tx: broadcast::Sender<ChatMessage>,
+    server_event_tx: broadcast::Sender<ServerEvent>,
+    event_tx: broadcast::Sender<ConnectionEvent>,
+    status_tx: broadcast::Sender<MessageStatusUpdate>,
+    write: Mutex<Option<WsWrite>>,
+    outbox: std::sync::Mutex<Outbox>,
+    /// 保证同一时间只有一个任务在发送发件箱，以维持消息顺序
+    flush_lock: Mutex<()>,
+    resume: std::sync::Mutex<Option<ResumeSource>>,
+    tracked_chats: std::sync::Mutex<HashSet<ConversationKey>>,
+    /// 最近一次收到消息的时间戳，断线后从这里开始补拉
+    last_message_at: AtomicI64,
+    /// 当前连接最近一次收到任意数据帧的时间
+    last_inbound_at: std::sync::Mutex<Instant>,
+    /// 最近一次发送 Ping 的时间，用于计算往返时间
+    last_ping_at: std::sync::Mutex<Option<Instant>>,
+}
+
+/// WebSocket 客户端。所有方法只需要 `&self`，可以直接在多个任务间共享，
+/// 界面线程读取发件箱时不会被正在进行的网络读写阻塞
+pub struct WebSocketClient {
+    shared: Arc<Shared>,
+    handle: std::sync::Mutex<Option<JoinHandle<()>>>,
+}
+
+impl WebSocketClient {
+    pub fn new(url: String, token: String) -> Self {
+        Self::with_config(url, token, WebSocketConfig::default())
+    }
+
+    pub fn with_config(url: String, token: String, config: WebSocketConfig) -> Self {
+        let (message_tx, _) = broadcast::channel(100);
+        let (server_event_tx, _) = broadcast::channel(100);
+        let (event_tx, _) = broadcast::channel(32);
+        let (state_tx, _) = watch::channel(ConnectionState::Disconnected);
+        let (status_tx, _) = broadcast::channel(100);
+        let (token_tx, _) = watch::channel(token);
+        Self {
+            shared: Arc::new(Shared {
+                url,
+                token_tx,
+                config,
+                state_tx,
+                message_tx,
+                server_event_tx,
+                event_tx,
+                status_tx,
+                write: Mutex::new(None),
+                outbox: std::sync::Mutex::new(Outbox::default()),
+                flush_lock: Mutex::new(()),
+                resume: std::sync::Mutex::new(None),
+                tracked_chats: std::sync::Mutex::new(HashSet::new()),
+                last_message_at: AtomicI64::new(0),
+                last_inbound_at: std::sync::Mutex::new(Instant::now()),
+                last_ping_at: std::sync::Mutex::new(None),
+            }),
+            handle: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// 设置断线重连后补拉离线消息所用的接口客户端，握手被拒绝时也用它刷新 token
+    pub fn set_resume_source(&self, network: Arc<NetworkClient>, user_id: i64) {
+        *self.shared.resume.lock().unwrap() = Some(ResumeSource { network, user_id });
+    }
+
+    /// 记录需要在重连后补拉消息的会话
+    pub fn track_chat(&self, key: ConversationKey) {
+        self.shared.tracked_chats.lock().unwrap().insert(key);
+    }
+
+    /// 设置持久化的发件箱，通常在登录后按账号加载
+    pub fn set_outbox(&self, outbox: Outbox) {
+        *self.shared.outbox.lock().unwrap() = outbox;
+    }
+
+    /// 发件箱中尚未成功发送的消息
+    pub fn outbox_entries(&self) -> Vec<OutboxEntry> {
+        self.shared.outbox.lock().unwrap().entries().to_vec()
+    }
+
+    /// 把消息放入发件箱并尝试发送，发送结果通过 `get_status_receiver` 通知。
+    /// 消息按调用顺序入队，需要在 tokio 运行时中调用
+    pub fn enqueue_message(&self, id: String, mut message: ChatMessage) {
+        message.client_id = Some(id.clone());
+        self.shared.outbox.lock().unwrap().push(id.clone(), message);
+        self.shared.notify_status(id, MessageStatus::Sending, None);
+        self.flush_outbox();
+    }
+
+    /// 手动重发一条失败的消息，需要在 tokio 运行时中调用
+    pub fn resend_message(&self, id: &str) {
+        if !self.shared.outbox.lock().unwrap().retry(id) {
+            println!("[错误] 发件箱中找不到消息: {}", id);
+            return;
+        }
+        self.shared
+            .notify_status(id.to_string(), MessageStatus::Sending, None);
+        self.flush_outbox();
+    }
+
+    /// 直接发送一个事件，不经过发件箱；用于输入状态这类丢失也无妨的事件
+    pub async fn send_event(&self, event: ClientEvent) -> Result<()> {
+        self.shared.write_event(&event).await
+    }
+
+    fn flush_outbox(&self) {
+        let shared = self.shared.clone();
+        tokio::spawn(async move {
+            shared.flush_outbox().await;
+        });
+    }
+
+    /// 启动连接监督任务，断线后会按退避策略自动重连。需要在 tokio 运行时中调用
+    pub fn connect(&self) -> Result<()> {
+        let mut handle = self.handle.lock().unwrap();
+        if handle.is_some() {
+            println!("[调试] WebSocket连接监督任务已在运行");
+            return Ok(());
+        }
+        // 提前校验URL，避免监督任务在无效地址上无限重试
+        Url::parse(&self.shared.ws_url())?;
+
+        let shared = self.shared.clone();
+        *handle = Some(tokio::spawn(async move {
+            shared.supervise().await;
+        }));
+        println!("[调试] WebSocket连接监督任务已启动");
+        Ok(())
+    }
+
+    /// 更新握手使用的 token 并用它重新连接。鉴权失败后监督任务已经停止的，重新启动
+    pub fn set_token(&self, token: String) {
+        if *self.shared.token_tx.borrow() == token {
+            return;
+        }
+        println!("[调试] WebSocket token已更新");
+        self.shared.token_tx.send_replace(token);
+        let finished = {
+            let mut handle = self.handle.lock().unwrap();
+            let finished = handle.as_ref().is_some_and(|handle| handle.is_finished());
+            if finished {
+                *handle = None;
+            }
+            finished
+        };
+        if finished {
+            if let Err(e) = self.connect() {
+                println!("[错误] 重新启动WebSocket连接失败: {}", e);
+            }
+        }
+    }
+
+    /// 主动断开连接：停止监督任务（不再自动重连），向服务端发送 Close 帧后关闭连接
+    pub async fn disconnect(&self) {
+        println!("[调试] 正在断开WebSocket连接");
+        let handle = self.handle.lock().unwrap().take();
+        if let Some(handle) = handle {
+            handle.abort();
+            // 等待监督任务真正退出，避免它在关闭连接后又写入新的连接
+            let _ = handle.await;
+        }
+        if let Some(mut write) = self.shared.write.lock().await.take() {
+            if let Err(e) = write.close().await {
+                println!("[错误] 关闭WebSocket连接失败: {}", e);
+            }
+        }
+        self.shared.set_state(ConnectionState::Disconnected);
+        println!("[调试] WebSocket连接已断开");
+    }
+
+    pub fn get_state_receiver(&self) -> watch::Receiver<ConnectionState> {
+        self.shared.state_tx.subscribe()
+    }
+
+    pub fn get_message_receiver(&self) -> broadcast::Receiver<ChatMessage> {
+        self.shared.message_tx.subscribe()
+    }
+
+    /// 订阅服务端推送的所有事件（聊天消息、确认以及尚未识别的事件）
+    pub fn get_event_receiver(&self) -> broadcast::Receiver<ServerEvent> {
+        self.shared.server_event_tx.subscribe()
+    }
+
+    pub fn get_status_receiver(&self) -> broadcast::Receiver<MessageStatusUpdate> {
+        self.shared.status_tx.subscribe()
+    }
+
+    pub fn get_connection_event_receiver(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.shared.event_tx.subscribe()
+    }
+}
+
+impl Shared {
+    fn ws_url(&self) -> String {
+        format!("{}/ws?token={}", self.url, *self.token_tx.borrow())
+    }
+
+    fn notify_status(&self, id: String, status: MessageStatus, error: Option<String>) {
+        let _ = self.status_tx.send(MessageStatusUpdate {
+            id,
+            status,
+            server_id: None,
+            error,
+        });
+    }
+
+    /// 收到确认或回显后把消息移出发件箱，返回该ID是否确实由本客户端发出
+    fn take_awaiting_ack(&self, client_id: &str) -> bool {
+        self.outbox.lock().unwrap().acknowledge(client_id)
+    }
+
+    /// 等待确认超时的消息放回发件箱的待发送状态，返回是否有需要重新发送的消息
+    fn requeue_unacked(&self) -> bool {
+        let timeout = self.config.ack_timeout.as_secs() as i64;
+        let expired = self
+            .outbox
+            .lock()
+            .unwrap()
+            .requeue_expired(chrono::Utc::now().timestamp(), timeout);
+        for id in &expired {
+            println!("[调试] 消息{}等待服务端确认超时，重新发送", id);
+        }
+        !expired.is_empty()
+    }
+
+    /// 按事件类型分发：聊天消息进入消息通道，确认帧更新投递状态，所有事件都会进入事件总线
+    fn dispatch_event(&self, event: ServerEvent) {
+        match &event {
+            ServerEvent::Message(message) => {
+                self.last_message_at
+                    .fetch_max(message.timestamp, Ordering::SeqCst);
+                // 服务端回显的自己发出的消息，界面上已有对应气泡，只当作确认处理
+                if let Some(client_id) = &message.client_id {
+                    if self.take_awaiting_ack(client_id) {
+                        println!("[调试] 收到消息回显: {}", client_id);
+                        self.notify_status(client_id.clone(), MessageStatus::Sent, None);
+                        return;
+                    }
+                }
+                let _ = self.message_tx.send(message.clone());
+            }
+            ServerEvent::Ack(ack) => self.handle_ack(ack.clone()),
+            ServerEvent::Typing(_)
+            | ServerEvent::Presence(_)
+            | ServerEvent::ReadReceipt(_)
+            | ServerEvent::Unknown(_) => {}
+        }
+        let _ = self.server_event_tx.send(event);
+    }
+
+    fn handle_ack(&self, ack: MessageAck) {
+        let status = match ack.status.as_deref() {
+            None => MessageStatus::Sent,
+            Some(status) => match MessageStatus::parse(status) {
+                Some(status) => status,
+                None => {
+                    println!("[错误] 未知的消息确认状态: {}", status);
+                    return;
+                }
+            },
+        };
+        // 送达/已读回执可能晚于首次确认到达，此时ID已经不在等待列表中
+        self.take_awaiting_ack(&ack.client_id);
+        let _ = self.status_tx.send(MessageStatusUpdate {
+            id: ack.client_id,
+            status,
+            server_id: ack.message_id,
+            error: None,
+        });
+    }
+
+    fn is_connected(&self) -> bool {
+        *self.state_tx.borrow() == ConnectionState::Connected
+    }
+
+    async fn write_event(&self, event: &ClientEvent) -> Result<()> {
+        if !self.is_connected() {
+            println!("[错误] 尝试发送消息时WebSocket未连接");
+            return Err(anyhow::anyhow!("WebSocket未连接"));
+        }
+
+        println!("[调试] 正在发送消息: {:?}", event);
+
+        let mut write = self.write.lock().await;
+        if let Some(write) = write.as_mut() {
+            let event_json = serde_json::to_string(event)?;
+            write.send(Message::Text(event_json)).await?;
+            println!("[调试] 消息发送成功");
+            Ok(())
+        } else {
+            println!("[错误] 发送消息时write通道为空");
+            Err(anyhow::anyhow!("发送通道未初始化"))
+        }
+    }
+
+    /// 按顺序发送发件箱中的消息；遇到失败即停止，等待下次重连后继续
+    async fn flush_outbox(&self) {
+        let _guard = self.flush_lock.lock().await;
+        while self.is_connected() {
+            let Some(entry) = self.outbox.lock().unwrap().next_pending() else {
+                break;
+            };
+            match self
+                .write_event(&ClientEvent::Message(entry.message.clone()))
+                .await
+            {
+                Ok(()) => {
+                    // 已写入连接，保留在发件箱中直到收到确认或回显
+                    self.outbox
+                        .lock()
+                        .unwrap()
+                        .mark_awaiting(&entry.id, chrono::Utc::now().timestamp());
+                }
+                Err(e) => {
+                    println!("[错误] 发送消息{}失败: {}", entry.id, e);
+                    let status = self.outbox.lock().unwrap().record_failure(&entry.id);
+                    self.notify_status(entry.id, status, Some(e.to_string()));
+                    break;
+                }
+            }
+        }
+    }
+
+    fn set_state(&self, state: ConnectionState) {
+        println!("[调试] 连接状态变更: {:?}", state);
+        self.state_tx.send_replace(state);
+    }
+
+    /// 连接监督循环：建立连接、监听消息，断开后按指数退避重连
+    async fn supervise(self: Arc<Self>) {
+        let mut attempt: u32 = 0;
+        let mut has_connected = false;
+        let mut token_rx = self.token_tx.subscribe();
+        self.set_state(ConnectionState::Connecting);
+        loop {
+            // 这次握手使用的 token，之后 token 再变化时需要重连
+            let token = token_rx.borrow_and_update().clone();
+            match self.open().await {
+                Ok(ws_stream) => {
+                    println!("[调试] WebSocket连接已建立");
+                    attempt = 0;
+                    let (write, read) = ws_stream.split();
+                    *self.write.lock().await = Some(write);
+                    *self.last_inbound_at.lock().unwrap() = Instant::now();
+                    *self.last_ping_at.lock().unwrap() = None;
+                    self.set_state(ConnectionState::Connected);
+                    let _ = self.event_tx.send(ConnectionEvent::Connected);
+
+                    // 连接恢复后按顺序补发离线期间积压的消息，
+                    // 上次连接上发出但没有确认的消息也重新发送
+                    self.outbox.lock().unwrap().reset_awaiting();
+                    let shared = self.clone();
+                    tokio::spawn(async move {
+                        shared.flush_outbox().await;
+                    });
+
+                    if has_connected {
+                        let since = self.last_message_at.load(Ordering::SeqCst);
+                        let shared = self.clone();
+                        tokio::spawn(async move {
+                            shared.resume_missed_messages(since).await;
+                        });
+                    } else {
+                        self.last_message_at
+                            .fetch_max(chrono::Local::now().timestamp(), Ordering::SeqCst);
+                    }
+                    has_connected = true;
+
+                    // 读循环与心跳任一结束，都视为本次连接结束
+                    let mut token_changed = false;
+                    let close_reason = tokio::select! {
+                        reason = self.read_loop(read) => reason,
+                        _ = self.heartbeat() => None,
+                        _ = token_rx.changed() => {
+                            token_changed = true;
+                            None
+                        }
+                    };
+
+                    if let Some(mut write) = self.write.lock().await.take() {
+                        let _ = write.close().await;
+                    }
+                    self.set_state(match close_reason {
+                        Some(reason) => ConnectionState::Closed(reason),
+                        None => ConnectionState::Disconnected,
+                    });
+                    let _ = self.event_tx.send(ConnectionEvent::Disconnected);
+                    println!("[调试] WebSocket连接断开");
+                    if token_changed {
+                        println!("[调试] token已更新，立即使用新token重连");
+                        continue;
+                    }
+                }
+                Err(e) => {
+                    println!("[错误] WebSocket连接失败: {}", e);
+                    if is_auth_error(&e) {
+                        match self.refresh_token(&token).await {
+                            Ok(()) => continue,
+                            // 暂时无法刷新，按退避策略稍后再试
+                            Err(e) if e.is_unreachable() => {}
+                            Err(_) => {
+                                println!("[错误] WebSocket鉴权失败，停止重连");
+                                self.set_state(ConnectionState::AuthFailed);
+                                return;
+                            }
+                        }
+                    }
+                }
+            }
+
+            attempt = attempt.saturating_add(1);
+            let delay = self.config.reconnect.delay_for(attempt);
+            println!(
+                "[调试] {:.1}秒后进行第{}次重连...",
+                delay.as_secs_f64(),
+                attempt
+            );
+            self.set_state(ConnectionState::Reconnecting(attempt));
+            let _ = self
+                .event_tx
+                .send(ConnectionEvent::RetryScheduled { attempt, delay });
+            // 等待期间 token 刷新了就不必等到退避结束
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = token_rx.changed() => {}
+            }
+        }
+    }
+
+    /// 握手被拒绝时用 refresh token 换取新 token，成功后由调用方立即重连
+    async fn refresh_token(&self, stale: &str) -> ApiResult<()> {
+        let network = match self.resume.lock().unwrap().as_ref() {
+            Some(source) => source.network.clone(),
+            None => return Err(ApiError::Unauthorized),
+        };
+        println!("[调试] WebSocket握手被拒绝，尝试刷新token");
+        network
+            .refresh(stale)
+            .await
+            .inspect_err(|e| println!("[错误] 刷新token失败: {}", e))?;
+        let token = network.get_token().ok_or(ApiError::Unauthorized)?;
+        self.token_tx.send_replace(token);
+        Ok(())
+    }
+
+    async fn open(&self) -> Result<WsStream> {
+        let ws_url = self.ws_url();
+        let url = Url::parse(&ws_url)?;
+        println!("[调试] 正在尝试连接WebSocket服务器: {}", self.url);
+
+        // 构建host头部
+        let host = if let Some(port) = url.port() {
+            format!("{}:{}", url.host_str().unwrap_or("localhost"), port)
+        } else {
+            url.host_str().unwrap_or("localhost").to_string()
+        };
+
+        let request = http::Request::builder()
+            .uri(ws_url)
+            .header("Host", host)
+            .header("Connection", "Upgrade")
+            .header("Upgrade", "websocket")
+            .header("Sec-WebSocket-Version", "13")
+            .header(
+                "Sec-WebSocket-Key",
+                STANDARD.encode(rand::random::<[u8; 16]>()),
+            )
+            .body(())?;
+
+        let (ws_stream, response) = connect_async(request).await?;
+        println!("[调试] WebSocket握手完成，响应状态: {}", response.status());
+        Ok(ws_stream)
+    }
+
+    /// 读取消息直到连接结束；若服务端发送了 Close 帧，返回关闭原因
+    async fn read_loop(&self, mut read: WsRead) -> Option<String> {
+        println!("[调试] 开始监听消息...");
+        while let Some(msg) = read.next().await {
+            let msg = match msg {
+                Ok(msg) => msg,
+                Err(e) => {
+                    println!("[错误] 接收消息失败: {}", e);
+                    break;
+                }
+            };
+            *self.last_inbound_at.lock().unwrap() = Instant::now();
+
+            match msg {
+                Message::Text(text_str) => {
+                    println!("[调试] 收到消息: {}", text_str);
+                    match ServerEvent::parse(&text_str) {
+                        Ok(event) => self.dispatch_event(event),
+                        Err(e) => println!("[错误] 解析消息失败: {}，内容: {}", e, text_str),
+                    }
+                }
+                Message::Ping(_) => {
+                    // tungstenite 收到 Ping 时会自动排队一个 Pong，这里主动 flush 让它立即发出
+                    if let Some(write) = self.write.lock().await.as_mut() {
+                        if let Err(e) = write.flush().await {
+                            println!("[错误] 回复Pong失败: {}", e);
+                        }
+                    }
+                    let _ = self.event_tx.send(ConnectionEvent::PingReceived);
+                }
+                Message::Pong(_) => {
+                    let rtt_ms = self
+                        .last_ping_at
+                        .lock()
+                        .unwrap()
+                        .take()
+                        .map(|sent| sent.elapsed().as_millis() as u64);
+                    let _ = self.event_tx.send(ConnectionEvent::PongReceived { rtt_ms });
+                }
+                Message::Close(frame) => {
+                    let (code, reason) = match frame {
+                        Some(CloseFrame { code, reason }) => (u16::from(code), reason.to_string()),
+                        None => (u16::from(CloseCode::Status), String::new()),
+                    };
+                    println!("[调试] 服务端关闭连接，代码: {}，原因: {}", code, reason);
+                    let description = if reason.is_empty() {
+                        code.to_string()
+                    } else {
+                        format!("{} {}", code, reason)
+                    };
+                    let _ = self.event_tx.send(ConnectionEvent::Closed { code, reason });
+                    return Some(description);
+                }
+                Message::Binary(data) => {
+                    println!("[调试] 忽略二进制消息，长度: {}", data.len());
+                }
+                Message::Frame(_) => {}
+            }
+        }
+        None
+    }
+
+    /// 周期性发送 Ping，超过超时时间没有收到任何数据时返回，由调用方断开连接
+    async fn heartbeat(&self) {
+        let heartbeat = &self.config.heartbeat;
+        let mut interval = tokio::time::interval(heartbeat.interval);
+        // 第一次 tick 会立即触发，跳过它
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if self.requeue_unacked() {
+                self.flush_outbox().await;
+            }
+
+            let idle = self.last_inbound_at.lock().unwrap().elapsed();
+            if idle > heartbeat.timeout {
+                println!(
+                    "[错误] 心跳超时，已有{:.1}秒未收到任何数据",
+                    idle.as_secs_f64()
+                );
+                let _ = self.event_tx.send(ConnectionEvent::HeartbeatTimeout);
+                return;
+            }
+
+            let mut write = self.write.lock().await;
+            let Some(write) = write.as_mut() else {
+                return;
+            };
+            if let Err(e) = write.send(Message::Ping(Vec::new())).await {
+                println!("[错误] 发送心跳失败: {}", e);
+                return;
+            }
+            self.last_ping_at
+                .lock()
+                .unwrap()
+                .get_or_insert_with(Instant::now);
+        }
+    }
+
+    /// 重连成功后，通过聊天记录接口补拉离线期间错过的消息
+    async fn resume_missed_messages(&self, since: i64) {
+        let (network, user_id) = match self.resume.lock().unwrap().as_ref() {
+            Some(source) => (source.network.clone(), source.user_id),
+            None => return,
+        };
+        let chats: Vec<ConversationKey> =
+            self.tracked_chats.lock().unwrap().iter().copied().collect();
+        println!(
+            "[调试] 正在补拉离线消息，会话数: {}，起始时间: {}",
+            chats.len(),
+            since
+        );
+
+        for key in chats {
+            // 从最新一页往前翻，直到翻到断线之前的消息
+            let mut messages = Vec::new();
+            let mut before_id = None;
+            loop {
+                let page = match network
+                    .get_chat_history(key.id, key.is_group, user_id, before_id, HISTORY_PAGE_SIZE)
+                    .await
+                {
+                    Ok(page) => page,
+                    Err(e) => {
+                        println!("[错误] 补拉会话{}的离线消息失败: {}", key.id, e);
+                        break;
+                    }
+                };
+                let reached_since = page.messages.first().is_none_or(|m| m.timestamp <= since);
+                before_id = page.messages.first().map(|m| m.id);
+                messages.extend(page.messages);
+                if reached_since || !page.has_more {
+                    break;
+                }
+            }
+
+            let mut missed: Vec<ChatMessage> = messages
+                .into_iter()
+                .filter(|m| m.timestamp > since && m.sender_id != user_id)
+                .map(ChatMessage::from)
+                .collect();
+            missed.sort_by_key(|m| m.timestamp);
+            for message in missed {
+                self.last_message_at
+                    .fetch_max(message.timestamp, Ordering::SeqCst);
+                let _ = self.message_tx.send(message);
+            }
+        }
+    }
+}
+
+/// 握手阶段被服务端以 401/403 拒绝时，说明 token 已失效，重试没有意义
+fn is_auth_error(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<WsError>(),
+        Some(WsError::Http(response))
+            if response.status() == http::StatusCode::UNAUTHORIZED
+                || response.status() == http::StatusCode::FORBIDDEN
+    )
+}
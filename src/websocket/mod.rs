@@ -0,0 +1,7 @@
+mod client;
+mod outbox;
+mod protocol;
+
+pub use client::*;
+pub use outbox::*;
+pub use protocol::*;
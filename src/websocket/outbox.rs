@@ -0,0 +1,401 @@
+use super::ChatMessage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// 单条消息最多自动重试的次数，超过后标记为失败，等待用户手动重发
+pub const MAX_SEND_ATTEMPTS: u32 = 3;
+
+/// 发出消息的投递状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MessageStatus {
+    /// 在发件箱中或已写入WebSocket，等待服务端确认
+    Sending,
+    /// 服务端已确认收到
+    Sent,
+    /// 已送达对方
+    Delivered,
+    /// 对方已读
+    Read,
+    /// 多次重试仍失败
+    Failed,
+}
+
+impl MessageStatus {
+    /// 界面上 `MessageItem.status` 使用的字符串
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageStatus::Sending => "sending",
+            MessageStatus::Sent => "sent",
+            MessageStatus::Delivered => "delivered",
+            MessageStatus::Read => "read",
+            MessageStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "sending" => Some(MessageStatus::Sending),
+            "sent" => Some(MessageStatus::Sent),
+            "delivered" => Some(MessageStatus::Delivered),
+            "read" => Some(MessageStatus::Read),
+            "failed" => Some(MessageStatus::Failed),
+            _ => None,
+        }
+    }
+
+    /// 状态是否可以覆盖当前状态；确认类状态只会前进，不会因乱序回退
+    pub fn supersedes(&self, current: MessageStatus) -> bool {
+        match (self, current) {
+            // 失败后手动重发会回到发送中
+            (MessageStatus::Sending, MessageStatus::Failed) => true,
+            (MessageStatus::Failed, _) => current == MessageStatus::Sending,
+            _ => self.rank() > current.rank(),
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            MessageStatus::Failed | MessageStatus::Sending => 0,
+            MessageStatus::Sent => 1,
+            MessageStatus::Delivered => 2,
+            MessageStatus::Read => 3,
+        }
+    }
+}
+
+/// 某条发出消息的状态变化
+#[derive(Debug, Clone)]
+pub struct MessageStatusUpdate {
+    /// 客户端消息ID
+    pub id: String,
+    pub status: MessageStatus,
+    /// 服务端确认后分配的消息ID
+    pub server_id: Option<i64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    /// 客户端消息ID，与 `ChatMessage.client_id` 一致
+    pub id: String,
+    pub message: ChatMessage,
+    pub attempts: u32,
+    pub status: MessageStatus,
+}
+
+/// 生成客户端消息ID，用于匹配服务端确认、回显以及界面上的消息气泡
+pub fn new_message_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// 待发送消息队列，按入队顺序发送，并持久化到磁盘以便重启后继续发送。
+/// 写入WebSocket后消息仍保留在队列中，收到确认或回显才移除
+#[derive(Default)]
+pub struct Outbox {
+    path: Option<PathBuf>,
+    entries: Vec<OutboxEntry>,
+    /// 已写入WebSocket、等待确认的消息和写入时间（Unix秒）。只在内存中，
+    /// 重启或重连后这些消息重新发送
+    awaiting: HashMap<String, i64>,
+}
+
+impl Outbox {
+    /// 从磁盘加载发件箱，文件不存在或损坏时返回空队列。
+    /// 上次运行时发出但没有等到确认的消息重新发送
+    pub fn load(path: PathBuf) -> Self {
+        let entries = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str::<Vec<OutboxEntry>>(&content).unwrap_or_else(|e| {
+                println!("[错误] 解析发件箱文件失败: {}", e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        println!(
+            "[调试] 已加载发件箱: {:?}，待发送消息数量: {}",
+            path,
+            entries.len()
+        );
+        Self {
+            path: Some(path),
+            entries,
+            awaiting: HashMap::new(),
+        }
+    }
+
+    fn persist(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = serde_json::to_string(&self.entries)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(path, content)?;
+                Ok(())
+            });
+        if let Err(e) = result {
+            println!("[错误] 保存发件箱失败: {}", e);
+        }
+    }
+
+    pub fn push(&mut self, id: String, message: ChatMessage) {
+        self.entries.push(OutboxEntry {
+            id,
+            message,
+            attempts: 0,
+            status: MessageStatus::Sending,
+        });
+        self.persist();
+    }
+
+    /// 记录消息已写入WebSocket，开始等待服务端确认
+    pub fn mark_awaiting(&mut self, id: &str, now: i64) {
+        if self.entries.iter().any(|entry| entry.id == id) {
+            self.awaiting.insert(id.to_string(), now);
+        }
+    }
+
+    /// 收到确认或回显后移除消息，返回该消息是否在发件箱中
+    pub fn acknowledge(&mut self, id: &str) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|entry| entry.id != id);
+        self.awaiting.remove(id);
+        let found = self.entries.len() != before;
+        if found {
+            self.persist();
+        }
+        found
+    }
+
+    /// 连接断开后，已发出但没有确认的消息可能没有到达服务端，重连后重新发送
+    pub fn reset_awaiting(&mut self) {
+        self.awaiting.clear();
+    }
+
+    /// 等待确认超过 `timeout` 秒的消息放回待发送状态，返回这些消息的ID。
+    /// 重新发送时沿用同一个客户端消息ID，服务端据此去重，不会变成失败让用户手动重发
+    pub fn requeue_expired(&mut self, now: i64, timeout: i64) -> Vec<String> {
+        let expired: Vec<String> = self
+            .awaiting
+            .iter()
+            .filter(|(_, since)| now - **since >= timeout)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &expired {
+            self.awaiting.remove(id);
+        }
+        expired
+    }
+
+    /// 记录一次发送失败，返回该消息的新状态
+    pub fn record_failure(&mut self, id: &str) -> MessageStatus {
+        let status = match self.entries.iter_mut().find(|entry| entry.id == id) {
+            Some(entry) => {
+                entry.attempts += 1;
+                if entry.attempts >= MAX_SEND_ATTEMPTS {
+                    entry.status = MessageStatus::Failed;
+                }
+                entry.status
+            }
+            None => MessageStatus::Failed,
+        };
+        self.persist();
+        status
+    }
+
+    /// 把失败的消息重新放回待发送状态，返回是否找到该消息
+    pub fn retry(&mut self, id: &str) -> bool {
+        let Some(entry) = self.entries.iter_mut().find(|entry| entry.id == id) else {
+            return false;
+        };
+        entry.attempts = 0;
+        entry.status = MessageStatus::Sending;
+        self.awaiting.remove(id);
+        self.persist();
+        true
+    }
+
+    pub fn next_pending(&self) -> Option<OutboxEntry> {
+        self.entries
+            .iter()
+            .find(|entry| {
+                entry.status == MessageStatus::Sending && !self.awaiting.contains_key(&entry.id)
+            })
+            .cloned()
+    }
+
+    pub fn entries(&self) -> &[OutboxEntry] {
+        &self.entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(content: &str) -> ChatMessage {
+        ChatMessage {
+            username: "alice".to_string(),
+            content: content.to_string(),
+            message_type: "text".to_string(),
+            sender_id: 1,
+            receiver_id: 2,
+            timestamp: 1_700_000_000,
+            target_type: "person".to_string(),
+            direction: "send".to_string(),
+            client_id: None,
+            message_id: None,
+            file_path: None,
+            file_name: None,
+            file_size: None,
+        }
+    }
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("me_chat_outbox_{}.json", new_message_id()))
+    }
+
+    #[test]
+    fn status_only_moves_forward() {
+        use MessageStatus::*;
+        assert!(Sent.supersedes(Sending));
+        assert!(Delivered.supersedes(Sent));
+        assert!(Read.supersedes(Delivered));
+        assert!(Read.supersedes(Sending));
+        assert!(!Sent.supersedes(Delivered));
+        assert!(!Delivered.supersedes(Read));
+        assert!(!Sent.supersedes(Sent));
+    }
+
+    #[test]
+    fn failed_only_replaces_sending() {
+        use MessageStatus::*;
+        assert!(Failed.supersedes(Sending));
+        assert!(!Failed.supersedes(Sent));
+        assert!(!Failed.supersedes(Read));
+        // 手动重发
+        assert!(Sending.supersedes(Failed));
+        // 超时后迟到的确认
+        assert!(Sent.supersedes(Failed));
+    }
+
+    #[test]
+    fn status_string_round_trip() {
+        use MessageStatus::*;
+        for status in [Sending, Sent, Delivered, Read, Failed] {
+            assert_eq!(MessageStatus::parse(status.as_str()), Some(status));
+        }
+        assert_eq!(MessageStatus::parse("pending"), None);
+    }
+
+    #[test]
+    fn load_restores_saved_entries() {
+        let path = temp_path();
+        let mut outbox = Outbox::load(path.clone());
+        outbox.push("a".to_string(), message("第一条"));
+        outbox.push("b".to_string(), message("第二条"));
+        outbox.record_failure("b");
+
+        let loaded = Outbox::load(path.clone());
+        let entries = loaded.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].id, "a");
+        assert_eq!(entries[0].message.content, "第一条");
+        assert_eq!(entries[1].attempts, 1);
+        assert_eq!(entries[1].status, MessageStatus::Sending);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn load_resends_unacknowledged_entries() {
+        let path = temp_path();
+        let mut outbox = Outbox::load(path.clone());
+        outbox.push("a".to_string(), message("你好"));
+        outbox.mark_awaiting("a", 100);
+        assert!(outbox.next_pending().is_none());
+
+        let loaded = Outbox::load(path.clone());
+        assert_eq!(
+            loaded.next_pending().map(|entry| entry.id),
+            Some("a".to_string())
+        );
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn acknowledged_entries_are_not_restored() {
+        let path = temp_path();
+        let mut outbox = Outbox::load(path.clone());
+        outbox.push("a".to_string(), message("你好"));
+        assert!(outbox.acknowledge("a"));
+        assert!(!outbox.acknowledge("a"));
+
+        assert!(Outbox::load(path.clone()).entries().is_empty());
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn corrupt_file_loads_empty() {
+        let path = temp_path();
+        std::fs::write(&path, "{not json").unwrap();
+        assert!(Outbox::load(path.clone()).entries().is_empty());
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn timed_out_entries_are_sent_again_with_same_id() {
+        let mut outbox = Outbox::default();
+        outbox.push("a".to_string(), message("一"));
+        outbox.push("b".to_string(), message("二"));
+        outbox.mark_awaiting("a", 100);
+        outbox.mark_awaiting("b", 120);
+        assert!(outbox.next_pending().is_none());
+
+        assert_eq!(outbox.requeue_expired(130, 30), vec!["a".to_string()]);
+        // 超时不算失败，不需要用户手动重发
+        assert!(outbox
+            .entries()
+            .iter()
+            .all(|entry| entry.status == MessageStatus::Sending));
+        let resent = outbox.next_pending().unwrap();
+        assert_eq!(resent.id, "a");
+        assert_eq!(resent.message.content, "一");
+
+        outbox.mark_awaiting("a", 130);
+        assert!(outbox.requeue_expired(140, 30).is_empty());
+        assert!(outbox.acknowledge("a"));
+        assert!(outbox.next_pending().is_none());
+    }
+
+    #[test]
+    fn reconnect_sends_unacknowledged_entries_again() {
+        let mut outbox = Outbox::default();
+        outbox.push("a".to_string(), message("一"));
+        outbox.mark_awaiting("a", 100);
+        outbox.reset_awaiting();
+        assert_eq!(
+            outbox.next_pending().map(|entry| entry.id),
+            Some("a".to_string())
+        );
+    }
+
+    #[test]
+    fn retry_resends_failed_entry() {
+        let mut outbox = Outbox::default();
+        outbox.push("a".to_string(), message("一"));
+        for _ in 0..MAX_SEND_ATTEMPTS {
+            outbox.record_failure("a");
+        }
+        assert_eq!(outbox.entries()[0].status, MessageStatus::Failed);
+        assert!(outbox.next_pending().is_none());
+        assert!(outbox.retry("a"));
+        assert_eq!(
+            outbox.next_pending().map(|entry| entry.id),
+            Some("a".to_string())
+        );
+        assert!(!outbox.retry("missing"));
+    }
+}
@@ -0,0 +1,245 @@
+use crate::api::MessageResponse;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatMessage {
+    pub username: String,
+    pub content: String,
+    pub message_type: String,
+    pub sender_id: i64,
+    pub receiver_id: i64,
+    pub timestamp: i64,
+    pub target_type: String,
+    pub direction: String,
+    /// 客户端生成的消息ID，服务端确认和回显时原样带回
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// 服务端分配的消息ID，用于已读回执
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<i64>,
+    /// 文件消息：上传后服务端返回的路径、文件名和字节数，`content` 同样为文件名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_size: Option<i64>,
+}
+
+impl From<MessageResponse> for ChatMessage {
+    fn from(message: MessageResponse) -> Self {
+        let (receiver_id, target_type) = match message.group_id {
+            Some(group_id) => (group_id, "group"),
+            None => (message.receiver_id.unwrap_or_default(), "person"),
+        };
+        Self {
+            username: message.username,
+            content: message.content,
+            message_type: message.message_type.unwrap_or_else(|| "text".to_string()),
+            sender_id: message.sender_id,
+            receiver_id,
+            timestamp: message.timestamp,
+            target_type: target_type.to_string(),
+            direction: message.direction,
+            client_id: None,
+            message_id: Some(message.id),
+            file_path: message.file_path,
+            file_name: message.file_name,
+            file_size: message.file_size,
+        }
+    }
+}
+
+/// 服务端对发出消息的确认：
+/// `{"type":"ack","client_id":"...","message_id":123,"status":"sent"}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageAck {
+    pub client_id: String,
+    pub message_id: Option<i64>,
+    #[serde(default)]
+    pub status: Option<String>,
+}
+
+/// 正在输入状态，`typing` 为 false 表示停止输入
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TypingEvent {
+    pub sender_id: i64,
+    pub receiver_id: i64,
+    pub target_type: String,
+    pub typing: bool,
+}
+
+/// 在线状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresenceStatus {
+    Online,
+    Away,
+    Offline,
+}
+
+impl PresenceStatus {
+    /// 界面上 `ChatItem.presence` 使用的字符串
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PresenceStatus::Online => "online",
+            PresenceStatus::Away => "away",
+            PresenceStatus::Offline => "offline",
+        }
+    }
+}
+
+/// 用户在线状态变化，`last_seen` 为最后在线时间戳（秒）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PresenceEvent {
+    pub user_id: i64,
+    pub status: PresenceStatus,
+    #[serde(default)]
+    pub last_seen: Option<i64>,
+}
+
+/// 已读回执：`reader_id` 已读完与 `chat_id` 的会话中ID不大于 `last_read_id` 的消息。
+/// 单聊时 `chat_id` 为对方ID，群聊时为群ID
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadReceipt {
+    pub reader_id: i64,
+    pub chat_id: i64,
+    pub target_type: String,
+    pub last_read_id: i64,
+}
+
+/// 服务端推送的事件，按 `type` 字段区分
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    Message(ChatMessage),
+    Ack(MessageAck),
+    Typing(TypingEvent),
+    Presence(PresenceEvent),
+    ReadReceipt(ReadReceipt),
+    /// 客户端尚不认识的事件，保留原始JSON
+    #[serde(skip)]
+    Unknown(Value),
+}
+
+impl ServerEvent {
+    /// 已识别的事件类型，与上面的变体一一对应
+    const KNOWN_TYPES: &'static [&'static str] =
+        &["message", "ack", "typing", "presence", "read_receipt"];
+
+    /// 解析一帧文本消息。没有 `type` 字段的旧格式按聊天消息处理，
+    /// 不认识的事件类型原样保留为 `Unknown`
+    pub fn parse(text: &str) -> serde_json::Result<Self> {
+        let value: Value = serde_json::from_str(text)?;
+        match value.get("type").and_then(Value::as_str) {
+            Some(kind) if Self::KNOWN_TYPES.contains(&kind) => serde_json::from_value(value),
+            Some(_) => Ok(ServerEvent::Unknown(value)),
+            None => match serde_json::from_value::<ChatMessage>(value.clone()) {
+                Ok(message) => Ok(ServerEvent::Message(message)),
+                Err(_) => Ok(ServerEvent::Unknown(value)),
+            },
+        }
+    }
+}
+
+/// 客户端发送给服务端的事件，按 `type` 字段区分
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientEvent {
+    Message(ChatMessage),
+    Typing(TypingEvent),
+    Presence(PresenceEvent),
+    ReadReceipt(ReadReceipt),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &str = r#""username":"alice","content":"你好","message_type":"text","sender_id":1,"receiver_id":2,"timestamp":1700000000,"target_type":"person","direction":"send""#;
+
+    #[test]
+    fn parses_tagged_message() {
+        let text = format!(
+            r#"{{"type":"message",{},"message_id":9007199254740993}}"#,
+            MESSAGE
+        );
+        match ServerEvent::parse(&text).unwrap() {
+            ServerEvent::Message(message) => {
+                assert_eq!(message.content, "你好");
+                assert_eq!(message.message_id, Some(9007199254740993));
+            }
+            other => panic!("解析结果不是聊天消息: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_untagged_message_as_chat_message() {
+        let text = format!("{{{}}}", MESSAGE);
+        assert!(matches!(
+            ServerEvent::parse(&text).unwrap(),
+            ServerEvent::Message(_)
+        ));
+    }
+
+    #[test]
+    fn parses_ack() {
+        let text = r#"{"type":"ack","client_id":"abc","message_id":42,"status":"sent"}"#;
+        match ServerEvent::parse(text).unwrap() {
+            ServerEvent::Ack(ack) => {
+                assert_eq!(ack.client_id, "abc");
+                assert_eq!(ack.message_id, Some(42));
+                assert_eq!(ack.status.as_deref(), Some("sent"));
+            }
+            other => panic!("解析结果不是确认: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_read_receipt() {
+        let text = r#"{"type":"read_receipt","reader_id":2,"chat_id":1,"target_type":"person","last_read_id":7}"#;
+        match ServerEvent::parse(text).unwrap() {
+            ServerEvent::ReadReceipt(receipt) => assert_eq!(receipt.last_read_id, 7),
+            other => panic!("解析结果不是已读回执: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn keeps_unknown_type_as_raw_json() {
+        let text = r#"{"type":"call_invite","from":3}"#;
+        match ServerEvent::parse(text).unwrap() {
+            ServerEvent::Unknown(value) => assert_eq!(value["from"], 3),
+            other => panic!("未知事件被解析成了: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn non_string_type_is_unknown() {
+        let text = r#"{"type":5,"from":3}"#;
+        assert!(matches!(
+            ServerEvent::parse(text).unwrap(),
+            ServerEvent::Unknown(_)
+        ));
+    }
+
+    #[test]
+    fn untagged_non_message_is_unknown() {
+        assert!(matches!(
+            ServerEvent::parse(r#"{"hello":"world"}"#).unwrap(),
+            ServerEvent::Unknown(_)
+        ));
+    }
+
+    #[test]
+    fn known_type_with_bad_body_is_error() {
+        assert!(ServerEvent::parse(r#"{"type":"ack","message_id":1}"#).is_err());
+        assert!(ServerEvent::parse(r#"{"type":"presence","user_id":1,"status":"busy"}"#).is_err());
+    }
+
+    #[test]
+    fn invalid_json_is_error() {
+        assert!(ServerEvent::parse("not json").is_err());
+        assert!(ServerEvent::parse("").is_err());
+    }
+}